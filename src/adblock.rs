@@ -0,0 +1,714 @@
+use crate::config::AdblockConfig;
+use crate::html::HtmlContent;
+use crate::links::{matches_domain, resolve};
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// What an item asks the reader to load or follow, matched against filter type options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Link,
+    Image,
+    Frame,
+}
+
+impl Kind {
+    fn bit(self) -> u8 {
+        match self {
+            Kind::Link => 1,
+            Kind::Image => 2,
+            Kind::Frame => 4,
+        }
+    }
+}
+
+const ALL_KINDS: u8 = 7;
+
+/// Kinds a type option applies to. Types an item never produces (scripts, XHR, ...)
+/// map to none, so filters limited to them never match. `None` for unknown options.
+fn kind_bits(option: &str) -> Option<u8> {
+    Some(match option {
+        "document" | "doc" | "popup" => Kind::Link.bit(),
+        "image" => Kind::Image.bit(),
+        "subdocument" | "frame" => Kind::Frame.bit(),
+        "all" => ALL_KINDS,
+        "script" | "stylesheet" | "css" | "object" | "object-subrequest" | "xmlhttprequest"
+        | "xhr" | "media" | "font" | "ping" | "websocket" | "webrtc" | "other" => 0,
+        _ => return None,
+    })
+}
+
+/// A link, image or frame of an item that a filter list blocks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Blocked {
+    pub url: String,
+    /// The filter as written in the list
+    pub filter: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    /// `^`: any character that cannot be part of a host or path word, or the end
+    Separator,
+    /// `*`
+    Any,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    /// `||`: matches at the start of the host or of any of its subdomains
+    host_anchor: bool,
+    /// Leading `|`
+    start_anchor: bool,
+    /// Trailing `|`
+    end_anchor: bool,
+    parts: Vec<Part>,
+}
+
+impl Pattern {
+    fn parse(src: &str) -> Self {
+        let (host_anchor, rest) = match src.strip_prefix("||") {
+            Some(r) => (true, r),
+            None => (false, src),
+        };
+        let (start_anchor, rest) = match rest.strip_prefix('|') {
+            Some(r) if !host_anchor => (true, r),
+            _ => (false, rest),
+        };
+        let (end_anchor, rest) = match rest.strip_suffix('|') {
+            Some(r) => (true, r),
+            None => (false, rest),
+        };
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        for c in rest.chars() {
+            let part = match c {
+                '*' => Part::Any,
+                '^' => Part::Separator,
+                _ => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            if !(part == Part::Any && parts.last() == Some(&Part::Any)) {
+                parts.push(part);
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        // Unanchored wildcards at either end change nothing.
+        if !host_anchor && !start_anchor && parts.first() == Some(&Part::Any) {
+            parts.remove(0);
+        }
+        if !end_anchor && parts.last() == Some(&Part::Any) {
+            parts.pop();
+        }
+        Self {
+            host_anchor,
+            start_anchor,
+            end_anchor,
+            parts,
+        }
+    }
+
+    /// The complete host a `||host^` style pattern is bound to, used as index key.
+    fn host_key(&self) -> Option<&str> {
+        if !self.host_anchor {
+            return None;
+        }
+        let Some(Part::Literal(lit)) = self.parts.first() else {
+            return None;
+        };
+        let (host, terminated) = match lit.find(['/', ':']) {
+            Some(i) => (&lit[..i], true),
+            None => (
+                lit.as_str(),
+                matches!(self.parts.get(1), Some(Part::Separator))
+                    || (self.parts.len() == 1 && self.end_anchor),
+            ),
+        };
+        let valid = host.contains('.')
+            && !host.starts_with('.')
+            && !host.ends_with('.')
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        (terminated && valid).then_some(host)
+    }
+
+    /// Whether the pattern is `||host^`, blocking a host as a whole.
+    fn is_whole_host(&self) -> bool {
+        self.host_key().is_some_and(|host| {
+            matches!(self.parts.as_slice(),
+                [Part::Literal(l)] | [Part::Literal(l), Part::Separator] if l == host)
+        })
+    }
+
+    fn matches(&self, url: &str) -> bool {
+        let s = url.as_bytes();
+        if self.host_anchor {
+            let Some(host_start) = url.find("://").map(|i| i + 3) else {
+                return false;
+            };
+            let host_end = url[host_start..]
+                .find(['/', '?', '#', ':'])
+                .map_or(url.len(), |i| host_start + i);
+            std::iter::once(host_start)
+                .chain(
+                    url[host_start..host_end]
+                        .match_indices('.')
+                        .map(|(i, _)| host_start + i + 1),
+                )
+                .any(|i| match_parts(&self.parts, &s[i..], self.end_anchor, false))
+        } else {
+            match_parts(&self.parts, s, self.end_anchor, !self.start_anchor)
+        }
+    }
+}
+
+/// Match `parts` against the start of `s`, or anywhere in it when `floating`.
+/// Only the last `*` is ever retried further along, so the cost stays linear in
+/// the pattern times the URL however many wildcards a filter has.
+fn match_parts(parts: &[Part], s: &[u8], end_anchor: bool, floating: bool) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // Part after the last `*` and where in `s` the `*` currently ends.
+    let mut star = floating.then_some((0, 0));
+    loop {
+        let step = match parts.get(p) {
+            None if !end_anchor || i == s.len() => return true,
+            None => None,
+            Some(Part::Any) => {
+                star = Some((p + 1, i));
+                p += 1;
+                continue;
+            }
+            Some(Part::Literal(lit)) => s[i..].starts_with(lit.as_bytes()).then_some(lit.len()),
+            Some(Part::Separator) => match s.get(i) {
+                None => Some(0),
+                Some(&b) => is_separator(b).then_some(1),
+            },
+        };
+        match (step, star) {
+            (Some(n), _) => {
+                p += 1;
+                i += n;
+            }
+            (None, Some((after, end))) if end < s.len() => {
+                star = Some((after, end + 1));
+                p = after;
+                i = end + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn is_separator(b: u8) -> bool {
+    !(b.is_ascii_alphanumeric() || b"_-.%".contains(&b))
+}
+
+#[derive(Debug, Clone)]
+struct Filter {
+    text: String,
+    pattern: Pattern,
+    match_case: bool,
+    kinds: u8,
+    third_party: Option<bool>,
+    /// `domain=` entries, `false` for excluded (`~`) domains
+    domains: Vec<(String, bool)>,
+}
+
+/// Why a line did not become a filter.
+enum Skip {
+    /// Comments, headers and element hiding rules
+    NotNetwork,
+    /// Regex filters and options whose meaning this matcher cannot honour
+    Unsupported,
+}
+
+impl Filter {
+    /// Parse one list line. Returns the filter and whether it is an exception (`@@`).
+    fn parse(line: &str) -> Result<(Self, bool), Skip> {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('!')
+            || line.starts_with('[')
+            || ["##", "#@#", "#?#", "#$#", "#%#"]
+                .iter()
+                .any(|m| line.contains(m))
+        {
+            return Err(Skip::NotNetwork);
+        }
+        let (exception, rule) = match line.strip_prefix("@@") {
+            Some(r) => (true, r),
+            None => (false, line),
+        };
+        let (pattern, options) = match rule.rfind('$') {
+            Some(i) => (&rule[..i], Some(&rule[i + 1..])),
+            None => (rule, None),
+        };
+        if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+            return Err(Skip::Unsupported);
+        }
+        let mut match_case = false;
+        let mut third_party = None;
+        let mut domains = Vec::new();
+        let mut include: Option<u8> = None;
+        let mut exclude = 0;
+        for option in options.into_iter().flat_map(|o| o.split(',')) {
+            let option = option.trim().to_lowercase();
+            let (negated, name) = match option.strip_prefix('~') {
+                Some(n) => (true, n),
+                None => (false, option.as_str()),
+            };
+            if let Some(list) = name.strip_prefix("domain=") {
+                domains = list
+                    .split('|')
+                    .filter(|d| !d.is_empty())
+                    .map(|d| match d.strip_prefix('~') {
+                        Some(d) => (d.to_string(), false),
+                        None => (d.to_string(), true),
+                    })
+                    .collect();
+                continue;
+            }
+            match name {
+                "third-party" | "3p" => third_party = Some(!negated),
+                "first-party" | "1p" => third_party = Some(negated),
+                "match-case" => match_case = true,
+                "important" => {}
+                _ => {
+                    let bits = kind_bits(name).ok_or(Skip::Unsupported)?;
+                    if negated {
+                        exclude |= bits;
+                    } else {
+                        *include.get_or_insert(0) |= bits;
+                    }
+                }
+            }
+        }
+        let pattern = if match_case {
+            Pattern::parse(pattern)
+        } else {
+            Pattern::parse(&pattern.to_lowercase())
+        };
+        // Lists block resources by default. Following a link is blocked only by filters
+        // that ask for it (`$document`, `$popup`) or that block a whole host (`||host^`).
+        let default_kinds = if exception || pattern.is_whole_host() {
+            ALL_KINDS
+        } else {
+            ALL_KINDS & !Kind::Link.bit()
+        };
+        let kinds = include.unwrap_or(default_kinds) & !exclude;
+        if kinds == 0 {
+            return Err(Skip::NotNetwork);
+        }
+        // An empty pattern without a domain restriction would match everything.
+        if pattern.parts.is_empty() && domains.is_empty() {
+            return Err(Skip::Unsupported);
+        }
+        let filter = Filter {
+            text: line.to_string(),
+            pattern,
+            match_case,
+            kinds,
+            third_party,
+            domains,
+        };
+        Ok((filter, exception))
+    }
+
+    fn applies(&self, req: &Request) -> bool {
+        self.kinds & req.kind.bit() != 0
+            && self.third_party.is_none_or(|t| t == req.third_party)
+            && self.domain_applies(req.source.as_deref())
+            && self.pattern.matches(if self.match_case {
+                &req.url
+            } else {
+                &req.lower
+            })
+    }
+
+    fn domain_applies(&self, source: Option<&str>) -> bool {
+        if self.domains.is_empty() {
+            return true;
+        }
+        let Some(source) = source else {
+            return false;
+        };
+        let on = |included: bool| {
+            self.domains
+                .iter()
+                .filter(|(_, inc)| *inc == included)
+                .map(|(d, _)| d.as_str())
+                .collect::<Vec<_>>()
+        };
+        let (includes, excludes) = (on(true), on(false));
+        !matches_domain(source, &excludes)
+            && (includes.is_empty() || matches_domain(source, &includes))
+    }
+}
+
+/// One URL taken from an item.
+struct Request {
+    url: String,
+    lower: String,
+    host: String,
+    kind: Kind,
+    third_party: bool,
+    /// Host of the item itself, for `domain=`
+    source: Option<String>,
+}
+
+/// Filters bound to a host are looked up by host and its parent domains; the rest
+/// are tried one by one.
+#[derive(Debug, Default)]
+struct FilterSet {
+    by_host: HashMap<String, Vec<Filter>>,
+    generic: Vec<Filter>,
+}
+
+impl FilterSet {
+    fn insert(&mut self, filter: Filter) {
+        match filter.pattern.host_key() {
+            Some(host) => self
+                .by_host
+                .entry(host.to_string())
+                .or_default()
+                .push(filter),
+            None => self.generic.push(filter),
+        }
+    }
+
+    fn find(&self, req: &Request) -> Option<&Filter> {
+        let mut host = req.host.as_str();
+        loop {
+            if let Some(found) = self
+                .by_host
+                .get(host)
+                .and_then(|filters| filters.iter().find(|f| f.applies(req)))
+            {
+                return Some(found);
+            }
+            match host.split_once('.') {
+                Some((_, parent)) => host = parent,
+                None => break,
+            }
+        }
+        self.generic.iter().find(|f| f.applies(req))
+    }
+}
+
+/// Compiled network filters of all configured lists.
+#[derive(Debug, Default)]
+pub struct Engine {
+    block: FilterSet,
+    allow: FilterSet,
+    filters: usize,
+    unsupported: usize,
+}
+
+impl Engine {
+    fn compile(sources: &[String]) -> Self {
+        let mut engine = Engine::default();
+        for src in sources {
+            for line in src.lines() {
+                match Filter::parse(line) {
+                    Ok((filter, true)) => {
+                        engine.filters += 1;
+                        engine.allow.insert(filter);
+                    }
+                    Ok((filter, false)) => {
+                        engine.filters += 1;
+                        engine.block.insert(filter);
+                    }
+                    Err(Skip::Unsupported) => engine.unsupported += 1,
+                    Err(Skip::NotNetwork) => {}
+                }
+            }
+        }
+        engine
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters == 0
+    }
+
+    /// Links, images and frames of an item blocked by the lists. Relative URLs are
+    /// resolved against `base`, which also decides what counts as third-party;
+    /// without it every request is third-party.
+    pub fn check(&self, html: &HtmlContent, base: Option<&str>) -> Vec<Blocked> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let base = base.and_then(|b| Url::parse(b).ok());
+        let source = base
+            .as_ref()
+            .and_then(|b| b.host_str())
+            .map(str::to_lowercase);
+        let requests = html
+            .links
+            .iter()
+            .map(|l| (l.href.as_str(), Kind::Link))
+            .chain(html.image_srcs.iter().map(|s| (s.as_str(), Kind::Image)))
+            .chain(html.iframes.iter().map(|s| (s.as_str(), Kind::Frame)));
+        let mut blocked = Vec::new();
+        for (href, kind) in requests {
+            let Some(url) = resolve(href, base.as_ref()) else {
+                continue;
+            };
+            let host = url.host_str().unwrap_or("").to_lowercase();
+            let url = url.to_string();
+            let req = Request {
+                lower: url.to_lowercase(),
+                third_party: source.as_deref().is_none_or(|s| site(s) != site(&host)),
+                url,
+                host,
+                kind,
+                source: source.clone(),
+            };
+            if let Some(filter) = self.block.find(&req)
+                && self.allow.find(&req).is_none()
+            {
+                blocked.push(Blocked {
+                    url: req.url,
+                    filter: filter.text.clone(),
+                });
+            }
+        }
+        blocked
+    }
+}
+
+/// Registrable part of a host: the last two labels, three under common
+/// second-level labels of country domains (`co.uk`, `com.cn`, ...).
+fn site(host: &str) -> &str {
+    let labels: Vec<&str> = host.rsplitn(4, '.').collect();
+    let keep = match labels.as_slice() {
+        [tld, second, _, ..]
+            if tld.len() == 2
+                && ["co", "com", "net", "org", "gov", "edu", "ac"].contains(second) =>
+        {
+            3
+        }
+        _ => 2,
+    };
+    let cut: usize = labels.iter().take(keep).map(|l| l.len() + 1).sum();
+    &host[host.len().saturating_sub(cut.saturating_sub(1))..]
+}
+
+struct Loaded {
+    mtimes: Vec<Option<SystemTime>>,
+    engine: Arc<Engine>,
+}
+
+/// The configured lists, compiled at startup and recompiled when a file changes.
+#[derive(Clone)]
+pub struct AdblockFilters {
+    paths: Vec<String>,
+    loaded: Arc<RwLock<Loaded>>,
+}
+
+impl AdblockFilters {
+    pub fn load(cfg: &AdblockConfig) -> Result<Self> {
+        let mtimes = modified(&cfg.lists);
+        let engine = compile(&cfg.lists)?;
+        info!(
+            lists = cfg.lists.len(),
+            filters = engine.filters,
+            unsupported = engine.unsupported,
+            "adblock_lists_loaded"
+        );
+        Ok(Self {
+            paths: cfg.lists.clone(),
+            loaded: Arc::new(RwLock::new(Loaded {
+                mtimes,
+                engine: Arc::new(engine),
+            })),
+        })
+    }
+
+    /// The compiled lists, recompiled first if any file changed since the last call.
+    /// A list that fails to load keeps the previous filters until it changes again.
+    pub fn current(&self) -> Arc<Engine> {
+        let mtimes = modified(&self.paths);
+        {
+            let loaded = self.loaded.read().expect("adblock lock");
+            if loaded.mtimes == mtimes {
+                return loaded.engine.clone();
+            }
+        }
+        let mut loaded = self.loaded.write().expect("adblock lock");
+        loaded.mtimes = mtimes;
+        match compile(&self.paths) {
+            Ok(engine) => {
+                info!(
+                    filters = engine.filters,
+                    unsupported = engine.unsupported,
+                    "adblock_lists_reloaded"
+                );
+                loaded.engine = Arc::new(engine);
+            }
+            Err(e) => warn!(error = %e, "adblock_reload_failed"),
+        }
+        loaded.engine.clone()
+    }
+}
+
+fn compile(paths: &[String]) -> Result<Engine> {
+    let sources = paths
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("adblock_list_read_failed: {}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Engine::compile(&sources))
+}
+
+fn modified(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html;
+
+    fn blocked(list: &str, item: &str, base: Option<&str>) -> Vec<String> {
+        Engine::compile(&[list.to_string()])
+            .check(&html::extract(item), base)
+            .into_iter()
+            .map(|b| b.url)
+            .collect()
+    }
+
+    fn image(src: &str) -> String {
+        format!(r#"<img src="{src}" width="300" height="200">"#)
+    }
+
+    #[test]
+    fn host_anchor_matches_host_and_subdomains() {
+        let list = "||ads.example^";
+        assert_eq!(
+            blocked(list, &image("https://cdn.ads.example/a.png"), None),
+            ["https://cdn.ads.example/a.png"]
+        );
+        assert!(blocked(list, &image("https://badads.example/a.png"), None).is_empty());
+        assert!(blocked(list, &image("https://ads.example.org/a.png"), None).is_empty());
+        assert!(blocked(list, &image("https://news.example/ads.example/a.png"), None).is_empty());
+    }
+
+    #[test]
+    fn separator_matches_punctuation_and_end() {
+        let list = "/banner^";
+        assert_eq!(
+            blocked(list, &image("https://a.example/banner?x=1"), None).len(),
+            1
+        );
+        assert_eq!(
+            blocked(list, &image("https://a.example/banner"), None).len(),
+            1
+        );
+        assert!(blocked(list, &image("https://a.example/banners.png"), None).is_empty());
+    }
+
+    #[test]
+    fn pipe_anchors_bind_start_and_end() {
+        let start = "|https://track.";
+        assert_eq!(
+            blocked(start, &image("https://track.example/p.gif"), None).len(),
+            1
+        );
+        assert!(blocked(start, &image("https://a.example/?u=https://track.x"), None).is_empty());
+        let end = ".gif|";
+        assert_eq!(
+            blocked(end, &image("https://a.example/p.gif"), None).len(),
+            1
+        );
+        assert!(blocked(end, &image("https://a.example/p.gif?x"), None).is_empty());
+    }
+
+    #[test]
+    fn wildcards_do_not_backtrack_exponentially() {
+        let list = format!("{}b", "a*".repeat(30));
+        let url = format!("https://a.example/{}", "a".repeat(200));
+        assert!(blocked(&list, &image(&url), None).is_empty());
+        assert_eq!(
+            blocked(
+                "/ad*/img*.png",
+                &image("https://a.example/ads/x/img1.png"),
+                None
+            )
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn third_party_compares_sites() {
+        let list = "||cdn.example^$third-party";
+        let item = image("https://cdn.example/a.png");
+        assert_eq!(
+            blocked(list, &item, Some("https://news.example/post")).len(),
+            1
+        );
+        assert!(blocked(list, &item, Some("https://www.cdn.example/post")).is_empty());
+        let first = "||cdn.example^$~third-party";
+        assert!(blocked(first, &item, Some("https://news.example/post")).is_empty());
+    }
+
+    #[test]
+    fn domain_option_includes_and_excludes_sources() {
+        let list = "cdn.example/promo/$domain=news.example|~sports.news.example";
+        let item = image("https://cdn.example/promo/a.png");
+        assert_eq!(
+            blocked(list, &item, Some("https://www.news.example/")).len(),
+            1
+        );
+        assert!(blocked(list, &item, Some("https://sports.news.example/")).is_empty());
+        assert!(blocked(list, &item, Some("https://blog.example/")).is_empty());
+        assert!(blocked(list, &item, None).is_empty());
+    }
+
+    #[test]
+    fn exceptions_override_blocks() {
+        let list = "||ads.example^\n@@||ads.example/allowed/";
+        assert!(blocked(list, &image("https://ads.example/allowed/a.png"), None).is_empty());
+        assert_eq!(
+            blocked(list, &image("https://ads.example/other/a.png"), None).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn links_need_whole_host_or_document_filters() {
+        let link = r#"<a href="https://shop.example/ads/deal">deal</a>"#;
+        assert!(blocked("example/ads/", link, None).is_empty());
+        assert!(blocked("||shop.example/ads/", link, None).is_empty());
+        assert_eq!(blocked("example/ads/$document", link, None).len(), 1);
+        assert_eq!(blocked("||shop.example^", link, None).len(), 1);
+        assert_eq!(
+            blocked(
+                "example/ads/",
+                &image("https://shop.example/ads/a.png"),
+                None
+            )
+            .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::openai_client::OpenAiApiError;
+use anyhow::Result;
+use reqwest::StatusCode;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Prefix of the run note written when the breaker trips; the next run starts half-open.
+pub const OPEN_NOTE: &str = "circuit_open";
+
+/// Kind of provider failure that counts towards tripping the breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    Auth,
+    Quota,
+    Network,
+}
+
+impl FailureClass {
+    /// Classify an error returned by the LLM client. Errors specific to one item
+    /// (bad request, unparseable reply) return `None` and never trip the breaker.
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        if let Some(api) = err.downcast_ref::<OpenAiApiError>() {
+            if api.code() == Some("insufficient_quota") {
+                return Some(FailureClass::Quota);
+            }
+            return match api.status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Some(FailureClass::Auth),
+                StatusCode::PAYMENT_REQUIRED | StatusCode::TOO_MANY_REQUESTS => {
+                    Some(FailureClass::Quota)
+                }
+                s if s.is_server_error() || s == StatusCode::REQUEST_TIMEOUT => {
+                    Some(FailureClass::Network)
+                }
+                _ => None,
+            };
+        }
+        err.downcast_ref::<reqwest::Error>()
+            .filter(|e| e.is_timeout() || e.is_connect() || e.is_request() || e.is_body())
+            .map(|_| FailureClass::Network)
+    }
+}
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureClass::Auth => write!(f, "auth"),
+            FailureClass::Quota => write!(f, "quota"),
+            FailureClass::Network => write!(f, "network"),
+        }
+    }
+}
+
+/// Returned instead of calling the provider while the breaker is open.
+#[derive(Debug)]
+pub struct CircuitOpenError {
+    pub class: FailureClass,
+}
+
+impl fmt::Display for CircuitOpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit_open: {}", self.class)
+    }
+}
+
+impl std::error::Error for CircuitOpenError {}
+
+/// Stops calling the classifier after `threshold` consecutive failures of the same class.
+/// When the previous run tripped, the breaker starts half-open: a single probe call goes
+/// through first and the rest wait for its outcome.
+pub struct CircuitBreaker {
+    threshold: u32,
+    state: Mutex<BreakerState>,
+    probe: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    half_open: bool,
+    streak: Option<(FailureClass, u32)>,
+    open: Option<Trip>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trip {
+    pub class: FailureClass,
+    pub failures: u32,
+    pub last_error: String,
+}
+
+impl fmt::Display for Trip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} after {} consecutive failures ({})",
+            OPEN_NOTE, self.class, self.failures, self.last_error
+        )
+    }
+}
+
+impl CircuitBreaker {
+    /// `threshold = 0` disables the breaker.
+    pub fn new(threshold: u32, half_open: bool) -> Self {
+        if half_open && threshold > 0 {
+            info!("circuit_half_open_probing");
+        }
+        Self {
+            threshold,
+            state: Mutex::new(BreakerState {
+                half_open: half_open && threshold > 0,
+                ..Default::default()
+            }),
+            probe: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Why the breaker tripped in this run, if it did.
+    pub fn trip(&self) -> Option<Trip> {
+        self.state.lock().expect("breaker lock").open.clone()
+    }
+
+    /// Run `call` unless the breaker is open, and record its outcome.
+    pub async fn call<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        if self.threshold == 0 {
+            return call.await;
+        }
+        let half_open = self.state.lock().expect("breaker lock").half_open;
+        let _probe = if half_open {
+            Some(self.probe.lock().await)
+        } else {
+            None
+        };
+        if let Some(trip) = self.trip() {
+            return Err(CircuitOpenError { class: trip.class }.into());
+        }
+        let res = call.await;
+        let mut st = self.state.lock().expect("breaker lock");
+        match &res {
+            Ok(_) => {
+                if st.half_open {
+                    info!("circuit_closed");
+                    st.half_open = false;
+                }
+                st.streak = None;
+            }
+            Err(e) => {
+                let Some(class) = FailureClass::of(e) else {
+                    // The provider answered, so it is reachable: the probe succeeded.
+                    if st.half_open {
+                        info!("circuit_closed");
+                        st.half_open = false;
+                    }
+                    return res;
+                };
+                let failures = match st.streak {
+                    Some((c, n)) if c == class => n + 1,
+                    _ => 1,
+                };
+                st.streak = Some((class, failures));
+                // A failed probe reopens the breaker immediately.
+                if (failures >= self.threshold || st.half_open) && st.open.is_none() {
+                    let trip = Trip {
+                        class,
+                        failures,
+                        last_error: e.to_string().chars().take(200).collect(),
+                    };
+                    warn!(class = %class, failures, error = %e, "circuit_opened");
+                    st.open = Some(trip);
+                }
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    config::{BudgetConfig, Config},
+    db::Database,
+};
+use anyhow::{Result, anyhow};
+use chrono::{Datelike, Local, TimeZone, Utc};
+
+/// Spend so far in the current day and month, checked against the configured caps.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    cfg: BudgetConfig,
+    pub spent_day: f64,
+    pub spent_month: f64,
+}
+
+/// Which cap stopped the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetCap {
+    Daily,
+    Monthly,
+}
+
+impl std::fmt::Display for BudgetCap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetCap::Daily => write!(f, "daily"),
+            BudgetCap::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+impl Budget {
+    pub async fn load(cfg: &BudgetConfig, db: &Database) -> Result<Self> {
+        let (day, month) = period_starts();
+        Ok(Self {
+            cfg: cfg.clone(),
+            spent_day: db.spend_since(&day).await?,
+            spent_month: db.spend_since(&month).await?,
+        })
+    }
+
+    /// The cap that `extra` spend on top of the loaded totals would reach, if any.
+    pub fn exhausted(&self, extra: f64) -> Option<BudgetCap> {
+        if let Some(cap) = self.cfg.daily_usd
+            && self.spent_day + extra >= cap
+        {
+            return Some(BudgetCap::Daily);
+        }
+        if let Some(cap) = self.cfg.monthly_usd
+            && self.spent_month + extra >= cap
+        {
+            return Some(BudgetCap::Monthly);
+        }
+        None
+    }
+
+    pub fn daily_cap(&self) -> Option<f64> {
+        self.cfg.daily_usd
+    }
+
+    pub fn monthly_cap(&self) -> Option<f64> {
+        self.cfg.monthly_usd
+    }
+}
+
+/// Refuse a budget that could not be enforced: spend is only known for models with a
+/// `[pricing]` entry, so every model that may be called needs one.
+pub fn check_pricing(cfg: &Config) -> Result<()> {
+    if cfg.budget.daily_usd.is_none() && cfg.budget.monthly_usd.is_none() {
+        return Ok(());
+    }
+    let shadow = cfg.shadow.as_ref().map(|s| s.apply(&cfg.openai));
+    let models = std::iter::once(&cfg.openai)
+        .chain(shadow.as_ref())
+        .flat_map(|c| {
+            std::iter::once(&c.model).chain(
+                c.fallbacks
+                    .iter()
+                    .map(|f| f.model.as_ref().unwrap_or(&c.model)),
+            )
+        });
+    for model in models {
+        if !cfg.pricing.contains_key(model) {
+            return Err(anyhow!(
+                "budget_model_unpriced: {} has no [pricing] entry, so [budget] cannot be enforced",
+                model
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Start of the current local day and month, as RFC 3339 UTC timestamps
+/// comparable with the ones stored in the database.
+pub fn period_starts() -> (String, String) {
+    let today = Local::now().date_naive();
+    let to_utc = |d: chrono::NaiveDate| {
+        Local
+            .from_local_datetime(&d.and_hms_opt(0, 0, 0).expect("valid midnight"))
+            .earliest()
+            .map(|t| t.with_timezone(&Utc).to_rfc3339())
+            .unwrap_or_default()
+    };
+    let month_start = today.with_day(1).expect("day 1 exists");
+    (to_utc(today), to_utc(month_start))
+}
@@ -0,0 +1,345 @@
+use crate::{config::Config, db::Database};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::info;
+
+/// Maps the classifier's self-reported confidence to an estimated probability
+/// that the item really is an ad. Only verdicts with `is_ad = true` are calibrated,
+/// since those are the only ones that can cross the threshold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Calibration {
+    /// Logistic fit: `1 / (1 + exp(-(a * x + b)))`
+    Platt { a: f64, b: f64 },
+    /// Monotone step function, linearly interpolated between block centers
+    Isotonic { xs: Vec<f64>, ys: Vec<f64> },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CalibrationMethod {
+    Platt,
+    Isotonic,
+}
+
+impl Calibration {
+    pub fn fit(method: CalibrationMethod, samples: &[(f32, bool)]) -> Self {
+        match method {
+            CalibrationMethod::Platt => fit_platt(samples),
+            CalibrationMethod::Isotonic => fit_isotonic(samples),
+        }
+    }
+
+    pub fn apply(&self, raw: f32) -> f32 {
+        let x = raw.clamp(0.0, 1.0) as f64;
+        let p = match self {
+            Calibration::Platt { a, b } => 1.0 / (1.0 + (-(a * x + b)).exp()),
+            Calibration::Isotonic { xs, ys } => interpolate(xs, ys, x),
+        };
+        p.clamp(0.0, 1.0) as f32
+    }
+}
+
+/// Platt scaling with the usual target smoothing, fitted by Newton's method.
+fn fit_platt(samples: &[(f32, bool)]) -> Calibration {
+    let pos = samples.iter().filter(|(_, y)| *y).count() as f64;
+    let neg = samples.len() as f64 - pos;
+    let hi = (pos + 1.0) / (pos + 2.0);
+    let lo = 1.0 / (neg + 2.0);
+
+    let (mut a, mut b) = (1.0f64, 0.0f64);
+    for _ in 0..100 {
+        let (mut ga, mut gb) = (0.0, 0.0);
+        let (mut haa, mut hab, mut hbb) = (1e-9, 0.0, 1e-9);
+        for (x, y) in samples {
+            let x = *x as f64;
+            let t = if *y { hi } else { lo };
+            let p = 1.0 / (1.0 + (-(a * x + b)).exp());
+            let d = p - t;
+            let w = p * (1.0 - p);
+            ga += d * x;
+            gb += d;
+            haa += w * x * x;
+            hab += w * x;
+            hbb += w;
+        }
+        let det = haa * hbb - hab * hab;
+        if det.abs() < 1e-12 {
+            break;
+        }
+        let da = (hbb * ga - hab * gb) / det;
+        let db = (haa * gb - hab * ga) / det;
+        a -= da;
+        b -= db;
+        if da.abs() < 1e-9 && db.abs() < 1e-9 {
+            break;
+        }
+    }
+    Calibration::Platt { a, b }
+}
+
+/// Pool-adjacent-violators over samples sorted by confidence.
+fn fit_isotonic(samples: &[(f32, bool)]) -> Calibration {
+    let mut sorted: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|(x, y)| (*x as f64, if *y { 1.0 } else { 0.0 }))
+        .collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // (sum_x, sum_y, weight)
+    let mut blocks: Vec<(f64, f64, f64)> = Vec::new();
+    for (x, y) in sorted {
+        blocks.push((x, y, 1.0));
+        while blocks.len() > 1 {
+            let n = blocks.len();
+            let (last, prev) = (blocks[n - 1], blocks[n - 2]);
+            if prev.1 / prev.2 <= last.1 / last.2 {
+                break;
+            }
+            blocks.pop();
+            blocks[n - 2] = (prev.0 + last.0, prev.1 + last.1, prev.2 + last.2);
+        }
+    }
+    Calibration::Isotonic {
+        xs: blocks.iter().map(|b| b.0 / b.2).collect(),
+        ys: blocks.iter().map(|b| b.1 / b.2).collect(),
+    }
+}
+
+fn interpolate(xs: &[f64], ys: &[f64], x: f64) -> f64 {
+    match xs.iter().position(|&bx| bx >= x) {
+        None => ys.last().copied().unwrap_or(x),
+        Some(0) => ys[0],
+        Some(i) => {
+            let (x0, x1, y0, y1) = (xs[i - 1], xs[i], ys[i - 1], ys[i]);
+            if (x1 - x0).abs() < f64::EPSILON {
+                y1
+            } else {
+                y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+            }
+        }
+    }
+}
+
+/// Calibrations and thresholds in effect for a run.
+#[derive(Debug, Clone)]
+pub struct DecisionPolicy {
+    /// Persisted calibrations by model, applied to the verdicts that model gave
+    pub calibrations: HashMap<String, Calibration>,
+    /// `openai.threshold`, for models without a tuned threshold
+    pub threshold: f32,
+    /// Thresholds persisted by `tune --apply`, by model
+    pub tuned_thresholds: HashMap<String, f32>,
+    /// `openai.languages` thresholds, which take precedence over the model's threshold
+    pub language_thresholds: HashMap<String, f32>,
+    /// `grey_zone.threshold`: ads between it and the threshold are left to the reader
+    pub review_threshold: Option<f32>,
+}
+
+impl DecisionPolicy {
+    /// Build the policy from config and whatever has been persisted for each model.
+    pub async fn load(cfg: &Config, db: &Database) -> Result<Self> {
+        let mut policy = DecisionPolicy {
+            calibrations: HashMap::new(),
+            threshold: cfg.openai.threshold,
+            tuned_thresholds: HashMap::new(),
+            language_thresholds: cfg
+                .openai
+                .languages
+                .iter()
+                .filter_map(|(lang, o)| o.threshold.map(|t| (lang.clone(), t)))
+                .collect(),
+            review_threshold: cfg.grey_zone.threshold,
+        };
+        if !cfg.calibration.enabled {
+            return Ok(policy);
+        }
+        for (model, params) in db.calibrations().await? {
+            policy
+                .calibrations
+                .insert(model, serde_json::from_str(&params)?);
+        }
+        if cfg.calibration.use_tuned_threshold {
+            policy.tuned_thresholds = db.tuned_thresholds().await?.into_iter().collect();
+        }
+        Ok(policy)
+    }
+
+    /// Confidence after `model`'s calibration, or the raw value when none is fitted.
+    pub fn calibrate(&self, model: &str, confidence: f32) -> f32 {
+        match self.calibrations.get(model) {
+            Some(c) => c.apply(confidence),
+            None => confidence,
+        }
+    }
+
+    /// Threshold for `model`'s verdicts on items in `language`.
+    pub fn threshold_for(&self, model: &str, language: Option<&str>) -> f32 {
+        language
+            .and_then(|l| self.language_thresholds.get(l))
+            .or_else(|| self.tuned_thresholds.get(model))
+            .copied()
+            .unwrap_or(self.threshold)
+    }
+
+    pub fn is_flagged(
+        &self,
+        model: &str,
+        is_ad: bool,
+        confidence: f32,
+        language: Option<&str>,
+    ) -> bool {
+        is_ad && self.calibrate(model, confidence) >= self.threshold_for(model, language)
+    }
+
+    /// An ad verdict too weak to act on but strong enough for the reader to look at.
+    pub fn is_uncertain(
+        &self,
+        model: &str,
+        is_ad: bool,
+        confidence: f32,
+        language: Option<&str>,
+    ) -> bool {
+        let Some(review) = self.review_threshold else {
+            return false;
+        };
+        let confidence = self.calibrate(model, confidence);
+        is_ad && confidence >= review && confidence < self.threshold_for(model, language)
+    }
+}
+
+/// Fit a calibration for every model that has enough feedback and persist it.
+pub async fn calibrate(cfg: &Config, db: &Database, method: CalibrationMethod) -> Result<()> {
+    let rows = db.feedback_verdicts().await?;
+    let mut models: Vec<&str> = rows.iter().map(|r| r.model.as_str()).collect();
+    models.sort();
+    models.dedup();
+    if models.is_empty() {
+        return Err(anyhow!(
+            "calibration_no_feedback: record feedback on reviewed items first"
+        ));
+    }
+
+    for model in models {
+        let samples: Vec<(f32, bool)> = rows
+            .iter()
+            .filter(|r| r.model == model && r.is_ad)
+            .map(|r| (r.confidence, r.expected))
+            .collect();
+        if samples.len() < cfg.calibration.min_samples {
+            println!(
+                "{}: 样本不足 ({} < {})，跳过",
+                model,
+                samples.len(),
+                cfg.calibration.min_samples
+            );
+            continue;
+        }
+        let fitted = Calibration::fit(method, &samples);
+        db.save_calibration(model, &serde_json::to_string(&fitted)?, samples.len())
+            .await?;
+        info!(model, samples = samples.len(), ?method, "calibration_saved");
+        println!("{}: 已拟合 {} 条样本", model, samples.len());
+        for raw in [0.5f32, 0.6, 0.7, 0.8, 0.9, 1.0] {
+            println!("  {:.1} -> {:.3}", raw, fitted.apply(raw));
+        }
+    }
+    Ok(())
+}
+
+/// Recommend the lowest threshold whose precision on feedback reaches `target_precision`,
+/// and optionally persist it for `model`.
+pub async fn tune(
+    cfg: &Config,
+    db: &Database,
+    model: &str,
+    target_precision: f64,
+    apply: bool,
+) -> Result<()> {
+    let policy = DecisionPolicy::load(cfg, db).await?;
+    let rows: Vec<_> = db
+        .feedback_verdicts()
+        .await?
+        .into_iter()
+        .filter(|r| r.model == model)
+        .collect();
+    if rows.is_empty() {
+        return Err(anyhow!("tune_no_feedback: no feedback for model {}", model));
+    }
+    let positives = rows.iter().filter(|r| r.expected).count();
+
+    println!(
+        "model={} 样本={} 实际广告={} 校准={} 目标 precision>={:.3}",
+        model,
+        rows.len(),
+        positives,
+        if policy.calibrations.contains_key(model) {
+            "是"
+        } else {
+            "否"
+        },
+        target_precision
+    );
+    println!("  threshold  precision  recall  flagged");
+
+    let mut best: Option<(f32, f64, f64)> = None;
+    for step in (0..=100).rev() {
+        let t = step as f32 / 100.0;
+        let (mut tp, mut fp) = (0usize, 0usize);
+        for r in &rows {
+            if r.is_ad && policy.calibrate(model, r.confidence) >= t {
+                if r.expected {
+                    tp += 1;
+                } else {
+                    fp += 1;
+                }
+            }
+        }
+        if tp + fp == 0 {
+            continue;
+        }
+        let precision = tp as f64 / (tp + fp) as f64;
+        let recall = if positives == 0 {
+            0.0
+        } else {
+            tp as f64 / positives as f64
+        };
+        if step % 10 == 0 {
+            println!(
+                "  {:>9.2}  {:>9.3}  {:>6.3}  {:>7}",
+                t,
+                precision,
+                recall,
+                tp + fp
+            );
+        }
+        if precision >= target_precision {
+            best = Some((t, precision, recall));
+        }
+    }
+
+    let Some((threshold, precision, recall)) = best else {
+        println!("没有阈值能达到目标 precision");
+        return Ok(());
+    };
+    println!(
+        "推荐阈值 {:.2} (precision={:.3} recall={:.3})，当前阈值 {:.2}",
+        threshold,
+        precision,
+        recall,
+        policy.threshold_for(model, None)
+    );
+    if apply {
+        db.save_tuned_threshold(model, threshold, target_precision)
+            .await?;
+        if cfg.calibration.enabled && cfg.calibration.use_tuned_threshold {
+            println!("已保存，下次运行生效");
+        } else {
+            println!(
+                "已保存；需设置 calibration.enabled 和 calibration.use_tuned_threshold 才会生效"
+            );
+        }
+    }
+    Ok(())
+}
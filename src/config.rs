@@ -1,5 +1,8 @@
-use anyhow::Result;
+use crate::profile::ProfileConfig;
+use crate::template::PromptTemplates;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +12,581 @@ pub struct Config {
     pub scheduler: SchedulerConfig,
     pub database: DatabaseConfig,
     pub dry_run: bool,
+    /// Price per model name, used for cost reporting
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>,
+    /// Candidate classifier run alongside `[openai]` without acting on its verdicts
+    #[serde(default)]
+    pub shadow: Option<ClassifierOverride>,
+    #[serde(default)]
+    pub calibration: CalibrationConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+    #[serde(default)]
+    pub fewshot: FewShotConfig,
+    #[serde(default)]
+    pub input: InputConfig,
+    #[serde(default)]
+    pub links: LinksConfig,
+    #[serde(default)]
+    pub rules: RulesConfig,
+    #[serde(default)]
+    pub adblock: AdblockConfig,
+    #[serde(default)]
+    pub injection: InjectionConfig,
+    #[serde(default)]
+    pub fulltext: FullTextConfig,
+    #[serde(default)]
+    pub resolver: ResolverConfig,
+    #[serde(default)]
+    pub images: ImagesConfig,
+    /// Topic filters judged in the same classifier call, by name
+    #[serde(default)]
+    pub filters: HashMap<String, FilterProfile>,
+    #[serde(default)]
+    pub interest: InterestConfig,
+    #[serde(default)]
+    pub grey_zone: GreyZoneConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationConfig {
+    /// Apply the persisted calibration of the model that answered before comparing with the threshold
+    #[serde(default)]
+    pub enabled: bool,
+    /// Minimum feedback samples required to fit a calibration
+    #[serde(default = "default_calibration_min_samples")]
+    pub min_samples: usize,
+    /// Use the threshold persisted by `tune --apply` instead of `openai.threshold`
+    #[serde(default)]
+    pub use_tuned_threshold: bool,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_samples: default_calibration_min_samples(),
+            use_tuned_threshold: false,
+        }
+    }
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price for cached prompt tokens; defaults to `input`
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+/// Spending caps in USD. When a cap is reached, remaining items wait for the next period.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    #[serde(default)]
+    pub daily_usd: Option<f64>,
+    #[serde(default)]
+    pub monthly_usd: Option<f64>,
+}
+
+/// Circuit breaker around the classifier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerConfig {
+    /// Consecutive failures of one class (auth, quota, network) that abort the run; 0 disables
+    #[serde(default = "default_breaker_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_breaker_failure_threshold(),
+        }
+    }
+}
+
+fn default_breaker_failure_threshold() -> u32 {
+    5
+}
+
+/// Few-shot examples drawn from reviews that received feedback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FewShotConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Examples per prompt, split evenly between ads and non-ads when both are available
+    #[serde(default = "default_fewshot_k")]
+    pub k: usize,
+    /// Estimated tokens all examples of one prompt may use together
+    #[serde(default = "default_fewshot_max_tokens")]
+    pub max_tokens: u64,
+    /// Example text is cut to this many characters
+    #[serde(default = "default_fewshot_max_example_chars")]
+    pub max_example_chars: usize,
+}
+
+impl Default for FewShotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            k: default_fewshot_k(),
+            max_tokens: default_fewshot_max_tokens(),
+            max_example_chars: default_fewshot_max_example_chars(),
+        }
+    }
+}
+
+impl FewShotConfig {
+    /// Settings that shape the examples, or None when few-shot is off so older fingerprints still match.
+    pub fn fingerprint(&self) -> Option<serde_json::Value> {
+        self.enabled.then(|| {
+            serde_json::json!({
+                "k": self.k,
+                "max_tokens": self.max_tokens,
+                "max_example_chars": self.max_example_chars,
+            })
+        })
+    }
+}
+
+fn default_fewshot_k() -> usize {
+    4
+}
+
+fn default_fewshot_max_tokens() -> u64 {
+    1500
+}
+
+fn default_fewshot_max_example_chars() -> usize {
+    800
+}
+
+/// Budget for the item text sent to the classifier, in estimated tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputConfig {
+    /// Item text per request; longer items are truncated or chunked, 0 disables the limit
+    #[serde(default = "default_input_max_tokens")]
+    pub max_tokens: u64,
+    /// Share of the budget taken from the start of a truncated article, the rest from its end
+    #[serde(default = "default_input_head_ratio")]
+    pub head_ratio: f32,
+    /// Items above this are classified in chunks instead of truncated; 0 always truncates
+    #[serde(default = "default_input_chunk_above")]
+    pub chunk_above: u64,
+    /// Items needing more chunks are counted as oversized and not classified
+    #[serde(default = "default_input_max_chunks")]
+    pub max_chunks: usize,
+    /// Share of `max_tokens` held back because token counts are estimated, not tokenized
+    #[serde(default = "default_input_safety_margin")]
+    pub safety_margin: f32,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: default_input_max_tokens(),
+            head_ratio: default_input_head_ratio(),
+            chunk_above: default_input_chunk_above(),
+            max_chunks: default_input_max_chunks(),
+            safety_margin: default_input_safety_margin(),
+        }
+    }
+}
+
+fn default_input_max_tokens() -> u64 {
+    3000
+}
+
+fn default_input_head_ratio() -> f32 {
+    0.7
+}
+
+fn default_input_chunk_above() -> u64 {
+    12000
+}
+
+fn default_input_max_chunks() -> usize {
+    6
+}
+
+fn default_input_safety_margin() -> f32 {
+    0.2
+}
+
+/// Affiliate and tracker link analysis.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LinksConfig {
+    /// Signal list replacing the bundled `assets/link_signals.toml`, re-read every run
+    #[serde(default)]
+    pub list_path: Option<String>,
+}
+
+/// Deterministic decisions taken before the classifier is called.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RulesConfig {
+    /// Items whose link score reaches this are ads without asking the classifier
+    #[serde(default)]
+    pub affiliate_score: Option<f32>,
+    /// Items with a link, image or frame blocked by the `[adblock]` lists are ads
+    #[serde(default)]
+    pub adblock: bool,
+    /// Items whose title or text contains one of these phrases are ads, case-insensitive
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Items with a redirected link ending at one of these domains are ads
+    #[serde(default)]
+    pub destination_domains: Vec<String>,
+    /// Rule packs for items detected in a language, used instead of the rules above
+    #[serde(default)]
+    pub languages: HashMap<String, RulesConfig>,
+}
+
+/// Fetching the linked article for items that only carry a teaser.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FullTextConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Fetch when the item body has fewer characters than this
+    #[serde(default = "default_fulltext_min_chars")]
+    pub min_chars: usize,
+    /// Larger pages are not downloaded
+    #[serde(default = "default_fulltext_max_bytes")]
+    pub max_bytes: usize,
+    #[serde(default = "default_fulltext_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Only fetch from these domains and their subdomains; empty allows every domain
+    #[serde(default)]
+    pub allow_domains: Vec<String>,
+    /// Never fetch from these domains and their subdomains
+    #[serde(default)]
+    pub deny_domains: Vec<String>,
+    /// Feed ids to fetch for; empty means every feed
+    #[serde(default)]
+    pub feeds: Vec<i64>,
+    /// Feed ids never fetched for
+    #[serde(default)]
+    pub skip_feeds: Vec<i64>,
+}
+
+impl Default for FullTextConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_chars: default_fulltext_min_chars(),
+            max_bytes: default_fulltext_max_bytes(),
+            timeout_secs: default_fulltext_timeout_secs(),
+            allow_domains: Vec::new(),
+            deny_domains: Vec::new(),
+            feeds: Vec::new(),
+            skip_feeds: Vec::new(),
+        }
+    }
+}
+
+fn default_fulltext_min_chars() -> usize {
+    400
+}
+
+fn default_fulltext_max_bytes() -> usize {
+    2_000_000
+}
+
+fn default_fulltext_timeout_secs() -> u64 {
+    10
+}
+
+/// Ads below the threshold but above a second, lower one, left for the reader to decide.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GreyZoneConfig {
+    /// Lower threshold; unset disables the grey zone
+    #[serde(default)]
+    pub threshold: Option<f32>,
+    /// GReader label added to grey-zone items, which stay unread
+    #[serde(default = "default_grey_zone_label")]
+    pub label: String,
+}
+
+impl Default for GreyZoneConfig {
+    fn default() -> Self {
+        Self {
+            threshold: None,
+            label: default_grey_zone_label(),
+        }
+    }
+}
+
+fn default_grey_zone_label() -> String {
+    "Review?".to_string()
+}
+
+/// Promoting items that match the reader's interests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestConfig {
+    /// The reader's interests in plain language; empty disables interest scoring
+    #[serde(default)]
+    pub profile: String,
+    #[serde(default = "default_interest_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub action: InterestAction,
+    /// Label added by the `label` action
+    #[serde(default = "default_interest_label")]
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterestAction {
+    /// Star through GReader, or save through Fever without GReader credentials
+    #[default]
+    Star,
+    /// Add `label` through GReader
+    Label,
+}
+
+impl Default for InterestConfig {
+    fn default() -> Self {
+        Self {
+            profile: String::new(),
+            threshold: default_interest_threshold(),
+            action: InterestAction::default(),
+            label: default_interest_label(),
+        }
+    }
+}
+
+fn default_interest_threshold() -> f32 {
+    0.8
+}
+
+fn default_interest_label() -> String {
+    "Must Read".to_string()
+}
+
+/// A topic to filter besides ads, described in plain language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterProfile {
+    /// What matching items are about, as shown to the classifier
+    pub description: String,
+    #[serde(default = "default_filter_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub action: FilterAction,
+    /// Label added by the `label` action; defaults to the profile name
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Add the profile label and leave the item unread
+    #[default]
+    Label,
+    /// Add the profile label and mark the item read
+    LabelRead,
+    /// Mark the item read
+    MarkRead,
+    /// Only store the verdict
+    None,
+}
+
+fn default_filter_threshold() -> f32 {
+    0.7
+}
+
+/// Sending the lead images of items to vision-capable models.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImagesConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Images sent per item, in document order
+    #[serde(default = "default_images_max_images")]
+    pub max_images: usize,
+    /// Larger images are not downloaded
+    #[serde(default = "default_images_max_bytes")]
+    pub max_bytes: usize,
+    /// Longest side after downscaling, in pixels
+    #[serde(default = "default_images_max_dimension")]
+    pub max_dimension: u32,
+    /// Images with a shorter side than this are icons or spacers and skipped
+    #[serde(default = "default_images_min_dimension")]
+    pub min_dimension: u32,
+    /// JPEG quality of the downscaled image, 1-100
+    #[serde(default = "default_images_quality")]
+    pub quality: u8,
+    /// `detail` requested from the model: "low", "high" or "auto"
+    #[serde(default = "default_images_detail")]
+    pub detail: String,
+    #[serde(default = "default_images_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Feed ids to send images for; empty means every feed
+    #[serde(default)]
+    pub feeds: Vec<i64>,
+    /// Feed ids never sending images
+    #[serde(default)]
+    pub skip_feeds: Vec<i64>,
+}
+
+impl ImagesConfig {
+    /// Settings that shape the images sent, or None when images are off.
+    pub fn fingerprint(&self) -> Option<serde_json::Value> {
+        self.enabled.then(|| {
+            serde_json::json!({
+                "max_images": self.max_images,
+                "max_dimension": self.max_dimension,
+                "detail": self.detail,
+            })
+        })
+    }
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_images: default_images_max_images(),
+            max_bytes: default_images_max_bytes(),
+            max_dimension: default_images_max_dimension(),
+            min_dimension: default_images_min_dimension(),
+            quality: default_images_quality(),
+            detail: default_images_detail(),
+            timeout_secs: default_images_timeout_secs(),
+            feeds: Vec::new(),
+            skip_feeds: Vec::new(),
+        }
+    }
+}
+
+fn default_images_max_images() -> usize {
+    2
+}
+
+fn default_images_max_bytes() -> usize {
+    5_000_000
+}
+
+fn default_images_max_dimension() -> u32 {
+    768
+}
+
+fn default_images_min_dimension() -> u32 {
+    64
+}
+
+fn default_images_quality() -> u8 {
+    80
+}
+
+fn default_images_detail() -> String {
+    "low".to_string()
+}
+
+fn default_images_timeout_secs() -> u64 {
+    10
+}
+
+/// Following shortened and redirecting links to their destination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolverConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Follow every outbound link, not only known shorteners and redirectors
+    #[serde(default)]
+    pub resolve_all: bool,
+    /// Extra shortener and redirector domains
+    #[serde(default)]
+    pub domains: Vec<String>,
+    #[serde(default = "default_resolver_max_hops")]
+    pub max_hops: usize,
+    /// Per request
+    #[serde(default = "default_resolver_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Links followed per item
+    #[serde(default = "default_resolver_max_links")]
+    pub max_links: usize,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            resolve_all: false,
+            domains: Vec::new(),
+            max_hops: default_resolver_max_hops(),
+            timeout_secs: default_resolver_timeout_secs(),
+            max_links: default_resolver_max_links(),
+        }
+    }
+}
+
+fn default_resolver_max_hops() -> usize {
+    5
+}
+
+fn default_resolver_timeout_secs() -> u64 {
+    5
+}
+
+fn default_resolver_max_links() -> usize {
+    10
+}
+
+/// Handling of feed content that tries to instruct the classifier.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InjectionConfig {
+    /// What happens to items with instruction-like content or chat role markers
+    #[serde(default)]
+    pub action: InjectionAction,
+    /// GReader label added by `action = "label"`
+    #[serde(default = "default_injection_label")]
+    pub label: String,
+    /// Classify suspected items again with the suspicious lines removed; the verdict
+    /// leaning towards ad wins
+    #[serde(default)]
+    pub secondary_check: bool,
+    /// Extra phrases treated as instructions, matched case-insensitively
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionAction {
+    /// Only record the flag; the verdict decides as usual
+    #[default]
+    Classify,
+    /// Handle suspected items like ads whatever the verdict
+    Ad,
+    /// Label suspected items that are not ads and leave them unread
+    Label,
+}
+
+impl Default for InjectionConfig {
+    fn default() -> Self {
+        Self {
+            action: InjectionAction::default(),
+            label: default_injection_label(),
+            secondary_check: false,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+fn default_injection_label() -> String {
+    "Injection?".into()
+}
+
+/// Adblock Plus / EasyList network filters matched against item links and images.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdblockConfig {
+    /// Local list files, compiled at startup and recompiled when one changes
+    #[serde(default)]
+    pub lists: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,10 +600,200 @@ pub struct OpenAiConfig {
     pub temperature: Option<f32>,
     #[serde(default)]
     pub max_tokens: Option<u32>,
+    /// System message template; may use the same placeholders as `user_template`
     #[serde(default = "default_system_prompt")]
     pub system_prompt: String,
+    /// User message template with `{{title}}`, `{{text}}` and similar placeholders
+    #[serde(default)]
+    pub user_template: Option<String>,
     #[serde(default = "default_threshold")]
     pub threshold: f32,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Name recorded for this endpoint; defaults to the `api_base` host
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Endpoints tried in order when the ones before fail with a retryable or quota error
+    #[serde(default)]
+    pub fallbacks: Vec<ProviderConfig>,
+    /// How long a failed endpoint is skipped before it is tried again
+    #[serde(default = "default_provider_cooldown_secs")]
+    pub provider_cooldown_secs: u64,
+    /// Request shape for this endpoint; detected from the model and `api_base` when unset
+    #[serde(default)]
+    pub profile: Option<ProfileConfig>,
+    /// Overrides for items detected in a language, keyed by code (`zh`, `en`, `ja`, ...)
+    #[serde(default)]
+    pub languages: HashMap<String, LanguageOverride>,
+}
+
+/// Classifier settings for items in one language; unset fields keep the `[openai]` value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguageOverride {
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub threshold: Option<f32>,
+}
+
+/// A fallback classifier endpoint. Unset `model` uses `[openai].model`;
+/// unset `api_key` sends no authorization header.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub api_base: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub profile: Option<ProfileConfig>,
+}
+
+/// Retries for rate-limited, failing or unreachable API calls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Attempts per item, including the first one
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /// First backoff delay; doubles on every retry
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub base_delay_ms: u64,
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub max_delay_ms: u64,
+    /// Give up once retrying an item would exceed this many seconds in total
+    #[serde(default = "default_retry_max_total_secs")]
+    pub max_total_secs: u64,
+    /// Timeout for a single request
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            base_delay_ms: default_retry_base_delay_ms(),
+            max_delay_ms: default_retry_max_delay_ms(),
+            max_total_secs: default_retry_max_total_secs(),
+            request_timeout_secs: default_request_timeout_secs(),
+        }
+    }
+}
+
+/// Client-side limits shared by all concurrent workers. Unset means unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl OpenAiConfig {
+    /// MD5 of the prompt templates in effect, used to tell prompt revisions apart.
+    /// The built-in user template counts too, so revising it makes reviews stale.
+    pub fn prompt_fingerprint(&self) -> String {
+        let user = self
+            .user_template
+            .as_deref()
+            .unwrap_or(crate::template::DEFAULT_USER_TEMPLATE);
+        let mut src = format!("{}\n{}", self.system_prompt, user);
+        let mut languages: Vec<(&String, &String)> = self
+            .languages
+            .iter()
+            .filter_map(|(lang, o)| o.system_prompt.as_ref().map(|p| (lang, p)))
+            .collect();
+        languages.sort();
+        for (lang, prompt) in languages {
+            src.push_str(&format!("\n[{}]\n{}", lang, prompt));
+        }
+        format!("{:x}", md5::compute(src))
+    }
+
+    /// Classifier settings that influence a verdict, serialized as JSON. Thresholds are
+    /// left out: they only decide what to do with a verdict, which reclassify re-takes
+    /// from the stored confidence.
+    pub fn settings_fingerprint(&self) -> String {
+        let mut settings = serde_json::json!({
+            "temperature": self.temperature,
+            "max_tokens": self.max_tokens,
+        });
+        // Only reasoning effort changes verdicts; added when set so older fingerprints still match.
+        if let Some(effort) = self
+            .profile
+            .as_ref()
+            .and_then(|p| p.reasoning_effort.as_ref())
+        {
+            settings["reasoning_effort"] = serde_json::json!(effort);
+        }
+        settings.to_string()
+    }
+}
+
+/// Classifier settings layered over `[openai]`; unset fields keep the primary value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClassifierOverride {
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub api_base: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub user_template: Option<String>,
+    #[serde(default)]
+    pub threshold: Option<f32>,
+    #[serde(default)]
+    pub profile: Option<ProfileConfig>,
+    /// Fallback endpoints for this classifier; unlike other fields, `[openai]` ones are
+    /// not inherited, so its verdicts always come from the configured model
+    #[serde(default)]
+    pub fallbacks: Option<Vec<ProviderConfig>>,
+}
+
+impl ClassifierOverride {
+    pub fn apply(&self, base: &OpenAiConfig) -> OpenAiConfig {
+        let mut cfg = base.clone();
+        if let Some(v) = &self.api_key {
+            cfg.api_key = v.clone();
+        }
+        if let Some(v) = &self.api_base {
+            cfg.api_base = v.clone();
+        }
+        if let Some(v) = &self.model {
+            cfg.model = v.clone();
+        }
+        if self.temperature.is_some() {
+            cfg.temperature = self.temperature;
+        }
+        if self.max_tokens.is_some() {
+            cfg.max_tokens = self.max_tokens;
+        }
+        if let Some(v) = &self.system_prompt {
+            cfg.system_prompt = v.clone();
+        }
+        if self.user_template.is_some() {
+            cfg.user_template = self.user_template.clone();
+        }
+        if let Some(v) = self.threshold {
+            cfg.threshold = v;
+        }
+        if self.profile.is_some() {
+            cfg.profile = self.profile.clone();
+        }
+        cfg.fallbacks = self.fallbacks.clone().unwrap_or_default();
+        cfg
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +841,24 @@ fn default_system_prompt() -> String {
 fn default_threshold() -> f32 {
     0.5
 }
+fn default_provider_cooldown_secs() -> u64 {
+    300
+}
+fn default_retry_max_attempts() -> u32 {
+    5
+}
+fn default_retry_base_delay_ms() -> u64 {
+    500
+}
+fn default_retry_max_delay_ms() -> u64 {
+    30_000
+}
+fn default_retry_max_total_secs() -> u64 {
+    120
+}
+fn default_request_timeout_secs() -> u64 {
+    60
+}
 fn default_user_agent() -> String {
     "freshrss-filter/0.1".into()
 }
@@ -85,6 +871,9 @@ fn default_spam_label() -> String {
 fn default_cron() -> String {
     "0 */10 * * * *".into()
 }
+fn default_calibration_min_samples() -> usize {
+    30
+}
 fn default_db_path() -> String {
     "freshrss-filter.db".into()
 }
@@ -121,9 +910,35 @@ pub async fn load(custom_path: Option<&Path>) -> Result<Config> {
         cfg.database.path = default_db_path();
     }
 
+    // Fail at startup rather than on the first item.
+    PromptTemplates::compile(&cfg.openai)?;
+    if let Some(shadow) = &cfg.shadow {
+        PromptTemplates::compile(&shadow.apply(&cfg.openai)).context("shadow")?;
+    }
+    crate::links::LinkLists::load(&cfg.links)?;
+
     Ok(cfg)
 }
 
+/// Load a classifier config for evaluation: the `[openai]` table of `path`
+/// layered over `base`, so the file only needs the settings that differ.
+pub fn load_classifier(path: &Path, base: &OpenAiConfig) -> Result<OpenAiConfig> {
+    use config as cfg;
+
+    #[derive(Serialize)]
+    struct Base<'a> {
+        openai: &'a OpenAiConfig,
+    }
+
+    let settings = cfg::Config::builder()
+        .add_source(cfg::Config::try_from(&Base { openai: base })?)
+        .add_source(cfg::File::from(path))
+        .build()?;
+    let openai = settings.get::<OpenAiConfig>("openai")?;
+    PromptTemplates::compile(&openai).with_context(|| path.display().to_string())?;
+    Ok(openai)
+}
+
 impl Config {
     pub fn with_overrides(mut self, dry_run: bool) -> Self {
         if dry_run {
@@ -4,12 +4,158 @@ use sqlx::{
     Pool, Sqlite,
     sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
 };
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use crate::openai_client::{ClassifierVersion, TokenUsage};
+
 #[derive(Clone)]
 pub struct Database(pub Arc<Pool<Sqlite>>);
 
+/// A review row as written by the processor.
+pub struct ReviewRecord<'a> {
+    pub item_id: &'a str,
+    pub feed_id: Option<i64>,
+    pub hash: &'a str,
+    pub title: &'a str,
+    pub text: &'a str,
+    pub is_ad: bool,
+    pub confidence: f32,
+    pub reason: &'a str,
+    pub calibrated_confidence: Option<f32>,
+    pub version: &'a ClassifierVersion,
+    /// Endpoint that answered, which may be a fallback
+    pub provider: Option<&'a str>,
+    /// Comma-separated item ids of the few-shot examples in the prompt
+    pub fewshot_ids: Option<&'a str>,
+    /// Link analysis features as JSON
+    pub link_features: Option<&'a str>,
+    /// Instruction-like phrases or chat role markers were found in the content
+    pub injection_suspected: bool,
+    /// Detected language code of the item text
+    pub language: Option<&'a str>,
+    /// Value to the `[interest]` reader profile, when scored
+    pub interest: Option<f32>,
+    pub usage: TokenUsage,
+    pub cost: f64,
+}
+
+/// Summary of one processing run.
+#[derive(Debug, Clone, Default)]
+pub struct RunRecord {
+    pub started_at: String,
+    pub total: u64,
+    pub kept: u64,
+    pub acted: u64,
+    pub skipped: u64,
+    pub deferred: u64,
+    pub errors: u64,
+    pub usage: TokenUsage,
+    pub cost: f64,
+    /// Verdicts that needed lenient parsing, a repair round-trip, or could not be parsed
+    pub lenient_parses: u64,
+    pub repairs: u64,
+    pub parse_failures: u64,
+    /// Items too long to classify
+    pub oversized: u64,
+    /// Items starred or labeled for their interest score
+    pub promoted: u64,
+    pub note: Option<String>,
+}
+
+/// A stored run summary, as shown by the `stats` command.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredRun {
+    pub started_at: String,
+    pub total: i64,
+    pub kept: i64,
+    pub acted: i64,
+    pub deferred: i64,
+    pub errors: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+    pub lenient_parses: i64,
+    pub repairs: i64,
+    pub parse_failures: i64,
+    pub oversized: i64,
+    pub promoted: i64,
+    pub note: Option<String>,
+}
+
+/// Token and cost totals over a period.
+#[derive(Debug, Clone, Default, sqlx::FromRow)]
+pub struct UsageTotals {
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cached_tokens: i64,
+    pub cost: f64,
+}
+
+/// A previously stored review, as needed for reclassification.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredReview {
+    pub item_id: String,
+    pub is_ad: bool,
+    pub confidence: f32,
+    pub action: Option<String>,
+    pub language: Option<String>,
+    pub injection_suspected: Option<bool>,
+    /// Model that gave the verdict; unset on reviews stored before it was recorded
+    pub model: Option<String>,
+}
+
+/// Result of an earlier full-text fetch.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedArticle {
+    pub html: Option<String>,
+    pub error: Option<String>,
+    pub fetched_at: String,
+}
+
+/// Result of an earlier redirect resolution.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CachedRedirect {
+    pub final_url: Option<String>,
+    pub error: Option<String>,
+    pub resolved_at: String,
+}
+
+/// A primary review next to the shadow classifier's verdict for the same item.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ShadowPair {
+    pub item_id: String,
+    pub title: Option<String>,
+    pub is_ad: bool,
+    pub confidence: f32,
+    pub reason: String,
+    pub shadow_is_ad: bool,
+    pub shadow_confidence: f32,
+    pub shadow_reason: String,
+}
+
+/// The classifier's raw verdict next to the verdict supplied as feedback.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct FeedbackVerdict {
+    pub model: String,
+    pub is_ad: bool,
+    pub confidence: f32,
+    pub expected: bool,
+}
+
+/// A reviewed item whose correct verdict was supplied as feedback.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LabeledReview {
+    pub item_id: String,
+    pub feed_id: Option<i64>,
+    pub title: Option<String>,
+    pub text: String,
+    /// Verdict from feedback, not the classifier's.
+    pub is_ad: bool,
+}
+
 impl Database {
     pub async fn new(path: &str) -> Result<Self> {
         let opts = SqliteConnectOptions::from_str(&format!("sqlite://{}", path))?
@@ -45,6 +191,204 @@ impl Database {
             .execute(self.pool())
             .await?;
 
+        // Columns added after the initial schema; older databases get them on startup.
+        self.add_column_if_missing("reviews", "title", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "prompt_hash", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "model", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "api_base", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "settings", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "action", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "text", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "calibrated_confidence", "REAL")
+            .await?;
+        self.add_column_if_missing("reviews", "prompt_tokens", "INTEGER")
+            .await?;
+        self.add_column_if_missing("reviews", "completion_tokens", "INTEGER")
+            .await?;
+        self.add_column_if_missing("reviews", "cached_tokens", "INTEGER")
+            .await?;
+        self.add_column_if_missing("reviews", "cost", "REAL")
+            .await?;
+        self.add_column_if_missing("reviews", "provider", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "feed_id", "INTEGER")
+            .await?;
+        self.add_column_if_missing("reviews", "fewshot_ids", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "link_features", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "injection_suspected", "INTEGER")
+            .await?;
+        self.add_column_if_missing("reviews", "language", "TEXT")
+            .await?;
+        self.add_column_if_missing("reviews", "interest", "REAL")
+            .await?;
+
+        // Every LLM call, including shadow calls, for budget accounting.
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS llm_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                model TEXT NOT NULL,
+                purpose TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                cached_tokens INTEGER NOT NULL,
+                cost REAL NOT NULL,
+                created_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE INDEX IF NOT EXISTS idx_llm_usage_created ON llm_usage(created_at);"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at TEXT NOT NULL,
+                finished_at TEXT NOT NULL,
+                total INTEGER NOT NULL,
+                kept INTEGER NOT NULL,
+                acted INTEGER NOT NULL,
+                skipped INTEGER NOT NULL,
+                deferred INTEGER NOT NULL,
+                errors INTEGER NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                cached_tokens INTEGER NOT NULL,
+                cost REAL NOT NULL,
+                note TEXT
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+        for column in [
+            "lenient_parses",
+            "repairs",
+            "parse_failures",
+            "oversized",
+            "promoted",
+        ] {
+            self.add_column_if_missing("runs", column, "INTEGER NOT NULL DEFAULT 0")
+                .await?;
+        }
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS calibrations (
+                model TEXT PRIMARY KEY,
+                params TEXT NOT NULL,
+                samples INTEGER NOT NULL,
+                fitted_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS tuned_thresholds (
+                model TEXT PRIMARY KEY,
+                threshold REAL NOT NULL,
+                target_precision REAL NOT NULL,
+                tuned_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS shadow_reviews (
+                item_id TEXT PRIMARY KEY,
+                is_ad INTEGER NOT NULL,
+                confidence REAL NOT NULL,
+                reason TEXT NOT NULL,
+                prompt_hash TEXT NOT NULL,
+                model TEXT NOT NULL,
+                api_base TEXT NOT NULL,
+                settings TEXT NOT NULL,
+                reviewed_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS feedback (
+                item_id TEXT PRIMARY KEY,
+                is_ad INTEGER NOT NULL,
+                source TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS fulltext_cache (
+                url TEXT PRIMARY KEY,
+                html TEXT,
+                error TEXT,
+                fetched_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS filter_verdicts (
+                item_id TEXT NOT NULL,
+                filter TEXT NOT NULL,
+                matched INTEGER NOT NULL,
+                confidence REAL NOT NULL,
+                reviewed_at TEXT NOT NULL,
+                PRIMARY KEY (item_id, filter)
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+        // Whether the profile's action was taken on the item, which reclassify undoes.
+        self.add_column_if_missing("filter_verdicts", "applied", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+
+        sqlx::query(
+            r#"CREATE TABLE IF NOT EXISTS redirect_cache (
+                url TEXT PRIMARY KEY,
+                final_url TEXT,
+                hops INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                resolved_at TEXT NOT NULL
+            );"#,
+        )
+        .execute(self.pool())
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) -> Result<()> {
+        let cols: Vec<(String,)> =
+            sqlx::query_as(&format!("SELECT name FROM pragma_table_info('{}')", table))
+                .fetch_all(self.pool())
+                .await?;
+        if cols.iter().any(|(name,)| name == column) {
+            return Ok(());
+        }
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, decl
+        ))
+        .execute(self.pool())
+        .await?;
         Ok(())
     }
 
@@ -60,24 +404,478 @@ impl Database {
         Ok(rec.is_some())
     }
 
-    pub async fn save_review(
+    pub async fn save_review(&self, review: &ReviewRecord<'_>) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT OR REPLACE INTO reviews(item_id, feed_id, hash, title, text, is_ad, confidence, reason, calibrated_confidence, prompt_hash, model, api_base, settings, provider, fewshot_ids, link_features, injection_suspected, language, interest, prompt_tokens, completion_tokens, cached_tokens, cost, reviewed_at) VALUES(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)",
+        )
+        .bind(review.item_id)
+        .bind(review.feed_id)
+        .bind(review.hash)
+        .bind(review.title)
+        .bind(review.text)
+        .bind(if review.is_ad { 1 } else { 0 })
+        .bind(review.confidence)
+        .bind(review.reason)
+        .bind(review.calibrated_confidence)
+        .bind(&review.version.prompt_hash)
+        .bind(&review.version.model)
+        .bind(&review.version.api_base)
+        .bind(&review.version.settings)
+        .bind(review.provider)
+        .bind(review.fewshot_ids)
+        .bind(review.link_features)
+        .bind(if review.injection_suspected { 1 } else { 0 })
+        .bind(review.language)
+        .bind(review.interest)
+        .bind(review.usage.prompt_tokens as i64)
+        .bind(review.usage.completion_tokens as i64)
+        .bind(review.usage.cached_tokens as i64)
+        .bind(review.cost)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Append one LLM call to the usage ledger.
+    pub async fn record_usage(
+        &self,
+        model: &str,
+        purpose: &str,
+        usage: &TokenUsage,
+        cost: f64,
+    ) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT INTO llm_usage(model, purpose, prompt_tokens, completion_tokens, cached_tokens, cost, created_at) VALUES(?,?,?,?,?,?,?)",
+        )
+        .bind(model)
+        .bind(purpose)
+        .bind(usage.prompt_tokens as i64)
+        .bind(usage.completion_tokens as i64)
+        .bind(usage.cached_tokens as i64)
+        .bind(cost)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Total cost of LLM calls at or after `since` (RFC 3339).
+    pub async fn spend_since(&self, since: &str) -> Result<f64> {
+        Ok(self.usage_since(since).await?.cost)
+    }
+
+    pub async fn usage_since(&self, since: &str) -> Result<UsageTotals> {
+        let totals = sqlx::query_as::<_, UsageTotals>(
+            r#"SELECT COUNT(*) AS calls,
+                      COALESCE(SUM(prompt_tokens), 0) AS prompt_tokens,
+                      COALESCE(SUM(completion_tokens), 0) AS completion_tokens,
+                      COALESCE(SUM(cached_tokens), 0) AS cached_tokens,
+                      COALESCE(SUM(cost), 0.0) AS cost
+               FROM llm_usage WHERE created_at >= ?"#,
+        )
+        .bind(since)
+        .fetch_one(self.pool())
+        .await?;
+        Ok(totals)
+    }
+
+    pub async fn save_run(&self, run: &RunRecord) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT INTO runs(started_at, finished_at, total, kept, acted, skipped, deferred, errors, prompt_tokens, completion_tokens, cached_tokens, cost, lenient_parses, repairs, parse_failures, oversized, promoted, note) VALUES(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)",
+        )
+        .bind(&run.started_at)
+        .bind(now.to_rfc3339())
+        .bind(run.total as i64)
+        .bind(run.kept as i64)
+        .bind(run.acted as i64)
+        .bind(run.skipped as i64)
+        .bind(run.deferred as i64)
+        .bind(run.errors as i64)
+        .bind(run.usage.prompt_tokens as i64)
+        .bind(run.usage.completion_tokens as i64)
+        .bind(run.usage.cached_tokens as i64)
+        .bind(run.cost)
+        .bind(run.lenient_parses as i64)
+        .bind(run.repairs as i64)
+        .bind(run.parse_failures as i64)
+        .bind(run.oversized as i64)
+        .bind(run.promoted as i64)
+        .bind(&run.note)
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Note of the most recent run, used to resume a tripped circuit breaker half-open.
+    pub async fn last_run_note(&self) -> Result<Option<String>> {
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT note FROM runs ORDER BY id DESC LIMIT 1")
+                .fetch_optional(self.pool())
+                .await?;
+        Ok(row.and_then(|r| r.0))
+    }
+
+    pub async fn recent_runs(&self, limit: usize) -> Result<Vec<StoredRun>> {
+        let rows = sqlx::query_as::<_, StoredRun>(
+            r#"SELECT started_at, total, kept, acted, deferred, errors,
+                      prompt_tokens, completion_tokens, cost,
+                      lenient_parses, repairs, parse_failures, oversized, promoted, note
+               FROM runs ORDER BY id DESC LIMIT ?"#,
+        )
+        .bind(limit as i64)
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    /// Number of reviews per stored action.
+    pub async fn action_counts(&self) -> Result<Vec<(String, i64)>> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT COALESCE(action, 'unknown'), COUNT(*) FROM reviews GROUP BY 1 ORDER BY 2 DESC",
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    /// Store the shadow classifier's verdict. Shadow verdicts never drive actions.
+    pub async fn save_shadow_review(
         &self,
         item_id: &str,
-        hash: &str,
         is_ad: bool,
         confidence: f32,
         reason: &str,
+        version: &ClassifierVersion,
     ) -> Result<()> {
         let now: DateTime<Utc> = Utc::now();
-        sqlx::query("INSERT OR REPLACE INTO reviews(item_id, hash, is_ad, confidence, reason, reviewed_at) VALUES(?,?,?,?,?,?)")
+        sqlx::query(
+            "INSERT OR REPLACE INTO shadow_reviews(item_id, is_ad, confidence, reason, prompt_hash, model, api_base, settings, reviewed_at) VALUES(?,?,?,?,?,?,?,?,?)",
+        )
+        .bind(item_id)
+        .bind(if is_ad { 1 } else { 0 })
+        .bind(confidence)
+        .bind(reason)
+        .bind(&version.prompt_hash)
+        .bind(&version.model)
+        .bind(&version.api_base)
+        .bind(&version.settings)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Primary and shadow verdicts for items the given shadow version has reviewed.
+    pub async fn shadow_pairs(&self, shadow: &ClassifierVersion) -> Result<Vec<ShadowPair>> {
+        let rows = sqlx::query_as::<_, ShadowPair>(
+            r#"SELECT r.item_id, r.title, r.is_ad, r.confidence, r.reason,
+                      s.is_ad AS shadow_is_ad, s.confidence AS shadow_confidence, s.reason AS shadow_reason
+               FROM reviews r
+               JOIN shadow_reviews s ON s.item_id = r.item_id
+               WHERE s.prompt_hash = ? AND s.model = ?
+               ORDER BY s.reviewed_at DESC"#,
+        )
+        .bind(&shadow.prompt_hash)
+        .bind(&shadow.model)
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    /// Shadow reviews left out of `shadow_pairs`: those from another prompt or model,
+    /// and those of this version whose item has no primary review.
+    pub async fn shadow_skipped(&self, shadow: &ClassifierVersion) -> Result<(u64, u64)> {
+        let (other, unpaired): (i64, i64) = sqlx::query_as(
+            r#"SELECT COALESCE(SUM(NOT (s.prompt_hash IS ?1 AND s.model IS ?2)), 0),
+                      COALESCE(SUM(s.prompt_hash IS ?1 AND s.model IS ?2 AND r.item_id IS NULL), 0)
+               FROM shadow_reviews s
+               LEFT JOIN reviews r ON r.item_id = s.item_id"#,
+        )
+        .bind(&shadow.prompt_hash)
+        .bind(&shadow.model)
+        .fetch_one(self.pool())
+        .await?;
+        Ok((other as u64, unpaired as u64))
+    }
+
+    /// Record which action was taken for a reviewed item.
+    pub async fn set_review_action(&self, item_id: &str, action: &str) -> Result<()> {
+        sqlx::query("UPDATE reviews SET action = ? WHERE item_id = ?")
+            .bind(action)
             .bind(item_id)
-            .bind(hash)
-            .bind(if is_ad {1} else {0})
-            .bind(confidence)
-            .bind(reason)
-            .bind(now.to_rfc3339())
             .execute(self.pool())
             .await?;
         Ok(())
     }
+
+    /// Reviews produced by a classifier version other than `current`. Thresholds stored
+    /// by earlier versions are ignored: they change decisions, not verdicts.
+    pub async fn stale_reviews(&self, current: &ClassifierVersion) -> Result<Vec<StoredReview>> {
+        self.reviews_by_version(current, true).await
+    }
+
+    /// Reviews produced by `current`, whose decisions may still need re-taking.
+    pub async fn current_reviews(&self, current: &ClassifierVersion) -> Result<Vec<StoredReview>> {
+        self.reviews_by_version(current, false).await
+    }
+
+    async fn reviews_by_version(
+        &self,
+        current: &ClassifierVersion,
+        stale: bool,
+    ) -> Result<Vec<StoredReview>> {
+        let rows = sqlx::query_as::<_, StoredReview>(&format!(
+            r#"SELECT item_id, is_ad, confidence, action, language, injection_suspected, model FROM reviews
+               WHERE {}(prompt_hash IS ? AND model IS ? AND api_base IS ?
+                 AND json_remove(settings, '$.threshold', '$.language_thresholds') IS ?)
+               ORDER BY reviewed_at"#,
+            if stale { "NOT " } else { "" }
+        ))
+        .bind(&current.prompt_hash)
+        .bind(&current.model)
+        .bind(&current.api_base)
+        .bind(&current.settings)
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    /// Record the correct verdict for an item. `source` says where it came from.
+    pub async fn save_feedback(&self, item_id: &str, is_ad: bool, source: &str) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT OR REPLACE INTO feedback(item_id, is_ad, source, created_at) VALUES(?,?,?,?)",
+        )
+        .bind(item_id)
+        .bind(if is_ad { 1 } else { 0 })
+        .bind(source)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Ids of reviews whose stored action is `action` and that have no feedback yet.
+    pub async fn reviews_awaiting_feedback(&self, action: &str) -> Result<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            r#"SELECT r.item_id FROM reviews r
+               LEFT JOIN feedback f ON f.item_id = r.item_id
+               WHERE r.action = ? AND f.item_id IS NULL"#,
+        )
+        .bind(action)
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+
+    /// Reviews with feedback and stored item text, usable as a labeled dataset.
+    pub async fn labeled_reviews(&self) -> Result<Vec<LabeledReview>> {
+        let rows = sqlx::query_as::<_, LabeledReview>(
+            r#"SELECT r.item_id, r.feed_id, r.title, r.text, f.is_ad FROM reviews r
+               JOIN feedback f ON f.item_id = r.item_id
+               WHERE r.text IS NOT NULL
+               ORDER BY f.created_at"#,
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    /// Ads and total reviews per feed, with feedback overriding the classifier's verdict.
+    pub async fn feed_ad_rates(&self) -> Result<HashMap<i64, (u64, u64)>> {
+        let rows: Vec<(i64, i64, i64)> = sqlx::query_as(
+            r#"SELECT r.feed_id, SUM(COALESCE(f.is_ad, r.is_ad)), COUNT(*) FROM reviews r
+               LEFT JOIN feedback f ON f.item_id = r.item_id
+               WHERE r.feed_id IS NOT NULL
+               GROUP BY r.feed_id"#,
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(feed, ads, total)| (feed, (ads as u64, total as u64)))
+            .collect())
+    }
+
+    /// Raw verdicts of reviews that have feedback, for calibration and tuning.
+    pub async fn feedback_verdicts(&self) -> Result<Vec<FeedbackVerdict>> {
+        let rows = sqlx::query_as::<_, FeedbackVerdict>(
+            r#"SELECT r.model, r.is_ad, r.confidence, f.is_ad AS expected FROM reviews r
+               JOIN feedback f ON f.item_id = r.item_id
+               WHERE r.model IS NOT NULL AND r.provider IS NOT 'rules'"#,
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    pub async fn save_calibration(&self, model: &str, params: &str, samples: usize) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT OR REPLACE INTO calibrations(model, params, samples, fitted_at) VALUES(?,?,?,?)",
+        )
+        .bind(model)
+        .bind(params)
+        .bind(samples as i64)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Serialized calibrations, by model.
+    pub async fn calibrations(&self) -> Result<Vec<(String, String)>> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT model, params FROM calibrations")
+            .fetch_all(self.pool())
+            .await?;
+        Ok(rows)
+    }
+
+    pub async fn save_tuned_threshold(
+        &self,
+        model: &str,
+        threshold: f32,
+        target_precision: f64,
+    ) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT OR REPLACE INTO tuned_thresholds(model, threshold, target_precision, tuned_at) VALUES(?,?,?,?)",
+        )
+        .bind(model)
+        .bind(threshold)
+        .bind(target_precision)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Thresholds persisted by `tune --apply`, by model.
+    pub async fn tuned_thresholds(&self) -> Result<Vec<(String, f32)>> {
+        let rows: Vec<(String, f32)> =
+            sqlx::query_as("SELECT model, threshold FROM tuned_thresholds")
+                .fetch_all(self.pool())
+                .await?;
+        Ok(rows)
+    }
+
+    /// Cached full-text fetch for `url`: the extracted article HTML, or the error of a
+    /// failed attempt, with the time of the fetch.
+    pub async fn fulltext_cached(&self, url: &str) -> Result<Option<CachedArticle>> {
+        let rec = sqlx::query_as::<_, CachedArticle>(
+            "SELECT html, error, fetched_at FROM fulltext_cache WHERE url = ?",
+        )
+        .bind(url)
+        .fetch_optional(self.pool())
+        .await?;
+        Ok(rec)
+    }
+
+    pub async fn save_fulltext(
+        &self,
+        url: &str,
+        html: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT OR REPLACE INTO fulltext_cache(url, html, error, fetched_at) VALUES(?,?,?,?)",
+        )
+        .bind(url)
+        .bind(html)
+        .bind(error)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Cached resolution of `url`: where its redirects ended, or the error of a failed
+    /// attempt, with the time of the attempt.
+    pub async fn redirect_cached(&self, url: &str) -> Result<Option<CachedRedirect>> {
+        let rec = sqlx::query_as::<_, CachedRedirect>(
+            "SELECT final_url, error, resolved_at FROM redirect_cache WHERE url = ?",
+        )
+        .bind(url)
+        .fetch_optional(self.pool())
+        .await?;
+        Ok(rec)
+    }
+
+    pub async fn save_redirect(
+        &self,
+        url: &str,
+        final_url: Option<&str>,
+        hops: i64,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let now: DateTime<Utc> = Utc::now();
+        sqlx::query(
+            "INSERT OR REPLACE INTO redirect_cache(url, final_url, hops, error, resolved_at) VALUES(?,?,?,?,?)",
+        )
+        .bind(url)
+        .bind(final_url)
+        .bind(hops)
+        .bind(error)
+        .bind(now.to_rfc3339())
+        .execute(self.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Filter profiles whose action was taken on an item at its last review.
+    pub async fn applied_filters(&self, item_id: &str) -> Result<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT filter FROM filter_verdicts WHERE item_id = ? AND applied = 1 ORDER BY filter",
+        )
+        .bind(item_id)
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows.into_iter().map(|(f,)| f).collect())
+    }
+
+    /// Replace the filter verdicts of an item with `verdicts` of `(filter, matched, confidence)`,
+    /// so profiles missing from the reply or the config leave no stale row behind.
+    pub async fn save_filter_verdicts(
+        &self,
+        item_id: &str,
+        verdicts: &[(&str, bool, f32)],
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool().begin().await?;
+        sqlx::query("DELETE FROM filter_verdicts WHERE item_id = ?")
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await?;
+        for (filter, matched, confidence) in verdicts {
+            sqlx::query(
+                "INSERT INTO filter_verdicts(item_id, filter, matched, confidence, reviewed_at) VALUES(?,?,?,?,?)",
+            )
+            .bind(item_id)
+            .bind(filter)
+            .bind(*matched as i64)
+            .bind(confidence)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record that the actions of `filters` were taken on an item.
+    pub async fn mark_filters_applied(&self, item_id: &str, filters: &[String]) -> Result<()> {
+        for filter in filters {
+            sqlx::query("UPDATE filter_verdicts SET applied = 1 WHERE item_id = ? AND filter = ?")
+                .bind(item_id)
+                .bind(filter)
+                .execute(self.pool())
+                .await?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,408 @@
+use crate::{
+    config::{Config, OpenAiConfig},
+    db::Database,
+    freshrss::stored_body,
+    language,
+    openai_client::{Classification, OpenAiClient, TokenUsage},
+    template::PromptVars,
+    verdict::VerdictParseError,
+};
+use anyhow::{Context, Result, anyhow};
+use futures::stream::{self, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// One labeled example. Dataset files are JSONL with one of these per line.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvalExample {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub text: String,
+    /// Expected verdict
+    pub is_ad: bool,
+}
+
+#[derive(Debug)]
+pub struct EvalOptions {
+    pub dataset: Option<PathBuf>,
+    pub classifier: Option<PathBuf>,
+    pub compare: Option<PathBuf>,
+    pub concurrency: usize,
+    pub misclassified: PathBuf,
+}
+
+struct Outcome {
+    example: usize,
+    result: Result<Classification>,
+    latency: Duration,
+}
+
+struct EvalRun {
+    name: String,
+    cfg: OpenAiConfig,
+    outcomes: Vec<Outcome>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Confusion {
+    tp: u64,
+    fp: u64,
+    tn: u64,
+    fn_: u64,
+}
+
+impl Confusion {
+    fn at(run: &EvalRun, examples: &[EvalExample], threshold: f32) -> Self {
+        let mut c = Confusion::default();
+        for o in &run.outcomes {
+            let Ok(res) = &o.result else { continue };
+            let predicted = res.verdict.is_ad && res.verdict.confidence >= threshold;
+            match (examples[o.example].is_ad, predicted) {
+                (true, true) => c.tp += 1,
+                (false, true) => c.fp += 1,
+                (false, false) => c.tn += 1,
+                (true, false) => c.fn_ += 1,
+            }
+        }
+        c
+    }
+
+    fn precision(&self) -> f64 {
+        ratio(self.tp, self.tp + self.fp)
+    }
+
+    fn recall(&self) -> f64 {
+        ratio(self.tp, self.tp + self.fn_)
+    }
+
+    fn f1(&self) -> f64 {
+        let (p, r) = (self.precision(), self.recall());
+        if p + r == 0.0 {
+            0.0
+        } else {
+            2.0 * p * r / (p + r)
+        }
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 { 0.0 } else { a as f64 / b as f64 }
+}
+
+pub async fn run(cfg: &Config, db: &Database, opts: EvalOptions) -> Result<()> {
+    let examples = match &opts.dataset {
+        Some(path) => load_dataset(path)?,
+        None => db
+            .labeled_reviews()
+            .await?
+            .into_iter()
+            .map(|r| EvalExample {
+                id: Some(r.item_id),
+                title: r.title,
+                text: r.text,
+                is_ad: r.is_ad,
+            })
+            .collect(),
+    };
+    if examples.is_empty() {
+        return Err(anyhow!(
+            "eval_dataset_empty: provide --dataset or record feedback first"
+        ));
+    }
+    info!(count = examples.len(), "eval_dataset_loaded");
+
+    let mut candidates = vec![match &opts.classifier {
+        Some(p) => (
+            p.display().to_string(),
+            crate::config::load_classifier(p, &cfg.openai)?,
+        ),
+        None => ("config".to_string(), cfg.openai.clone()),
+    }];
+    if let Some(p) = &opts.compare {
+        candidates.push((
+            p.display().to_string(),
+            crate::config::load_classifier(p, &cfg.openai)?,
+        ));
+    }
+
+    let mut runs = Vec::new();
+    for (name, classifier) in candidates {
+        let outcomes = classify_all(&classifier, &examples, opts.concurrency.max(1)).await?;
+        record_usage(cfg, db, &classifier.model, &outcomes).await?;
+        runs.push(EvalRun {
+            name,
+            cfg: classifier,
+            outcomes,
+        });
+    }
+
+    for run in &runs {
+        report(cfg, run, &examples);
+    }
+    if runs.len() > 1 {
+        compare(cfg, &runs, &examples);
+    }
+    write_misclassified(&opts.misclassified, &runs, &examples)?;
+    Ok(())
+}
+
+fn load_dataset(path: &Path) -> Result<Vec<EvalExample>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("eval_dataset_read_failed: {}", path.display()))?;
+    data.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, l)| {
+            serde_json::from_str::<EvalExample>(l)
+                .with_context(|| format!("eval_dataset_parse_failed: line {}", n + 1))
+        })
+        .collect()
+}
+
+async fn classify_all(
+    cfg: &OpenAiConfig,
+    examples: &[EvalExample],
+    concurrency: usize,
+) -> Result<Vec<Outcome>> {
+    let client = OpenAiClient::new(cfg.clone())?;
+    let pb = ProgressBar::new(examples.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("评估 {prefix} {pos}/{len} [{bar:40.cyan/blue}] {percent}% | 剩余~{eta}")
+            .expect("valid template")
+            .progress_chars("=>-"),
+    );
+    pb.set_prefix(cfg.model.clone());
+
+    let mut outcomes = stream::iter(examples.iter().enumerate())
+        .map(|(i, ex)| {
+            let client = client.clone();
+            let pb = pb.clone();
+            async move {
+                // Stored review text starts with the title, which the template adds itself.
+                let vars = PromptVars {
+                    title: ex.title.clone().unwrap_or_default(),
+                    text: stored_body(ex.title.as_deref(), &ex.text).to_string(),
+                    language: language::detect(&ex.text),
+                    ..Default::default()
+                };
+                let started = Instant::now();
+                let result = client.classify(&vars, &[]).await;
+                pb.inc(1);
+                Outcome {
+                    example: i,
+                    result,
+                    latency: started.elapsed(),
+                }
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    pb.finish_and_clear();
+    outcomes.sort_by_key(|o| o.example);
+    Ok(outcomes)
+}
+
+/// Book the calls of one candidate in `llm_usage`, so eval spend counts against the budget.
+async fn record_usage(
+    cfg: &Config,
+    db: &Database,
+    model: &str,
+    outcomes: &[Outcome],
+) -> Result<()> {
+    for o in outcomes {
+        let (model, usage) = match &o.result {
+            Ok(res) => (res.model.as_str(), res.usage),
+            Err(e) => match e.downcast_ref::<VerdictParseError>() {
+                Some(parse_err) if !parse_err.model.is_empty() => {
+                    (parse_err.model.as_str(), parse_err.usage)
+                }
+                Some(parse_err) => (model, parse_err.usage),
+                None => continue,
+            },
+        };
+        let cost = cfg.pricing.get(model).map(|p| usage.cost(p)).unwrap_or(0.0);
+        db.record_usage(model, "eval", &usage, cost).await?;
+    }
+    Ok(())
+}
+
+struct Summary {
+    confusion: Confusion,
+    errors: usize,
+    usage: TokenUsage,
+    cost: Option<f64>,
+    latency_mean: Duration,
+    latency_p95: Duration,
+}
+
+fn summarize(cfg: &Config, run: &EvalRun, examples: &[EvalExample]) -> Summary {
+    let mut usage = TokenUsage::default();
+    for o in &run.outcomes {
+        if let Ok(res) = &o.result {
+            usage.add(&res.usage);
+        }
+    }
+    let mut latencies: Vec<Duration> = run.outcomes.iter().map(|o| o.latency).collect();
+    latencies.sort();
+    let latency_mean = if latencies.is_empty() {
+        Duration::ZERO
+    } else {
+        latencies.iter().sum::<Duration>() / latencies.len() as u32
+    };
+    let latency_p95 = latencies
+        .get((latencies.len() * 95 / 100).min(latencies.len().saturating_sub(1)))
+        .copied()
+        .unwrap_or_default();
+    Summary {
+        confusion: Confusion::at(run, examples, run.cfg.threshold),
+        errors: run.outcomes.iter().filter(|o| o.result.is_err()).count(),
+        usage,
+        cost: cfg.pricing.get(&run.cfg.model).map(|p| usage.cost(p)),
+        latency_mean,
+        latency_p95,
+    }
+}
+
+fn report(cfg: &Config, run: &EvalRun, examples: &[EvalExample]) {
+    let s = summarize(cfg, run, examples);
+    let c = s.confusion;
+    println!();
+    println!(
+        "== {} (model={}, threshold={:.2}) ==",
+        run.name, run.cfg.model, run.cfg.threshold
+    );
+    println!(
+        "样本={} 出错={} precision={:.3} recall={:.3} f1={:.3}",
+        examples.len(),
+        s.errors,
+        c.precision(),
+        c.recall(),
+        c.f1()
+    );
+    println!("混淆矩阵:");
+    println!("              预测=广告  预测=正常");
+    println!("  实际=广告   {:>9}  {:>9}", c.tp, c.fn_);
+    println!("  实际=正常   {:>9}  {:>9}", c.fp, c.tn);
+    println!("阈值曲线:");
+    println!("  threshold  precision  recall     f1");
+    for step in 1..=9 {
+        let t = step as f32 / 10.0;
+        let c = Confusion::at(run, examples, t);
+        println!(
+            "  {:>9.1}  {:>9.3}  {:>6.3}  {:>5.3}",
+            t,
+            c.precision(),
+            c.recall(),
+            c.f1()
+        );
+    }
+    println!(
+        "tokens: prompt={} completion={} | 费用={} | 延迟 平均={}ms p95={}ms",
+        s.usage.prompt_tokens,
+        s.usage.completion_tokens,
+        s.cost
+            .map(|c| format!("${:.4}", c))
+            .unwrap_or_else(|| "未配置价格".into()),
+        s.latency_mean.as_millis(),
+        s.latency_p95.as_millis(),
+    );
+}
+
+fn compare(cfg: &Config, runs: &[EvalRun], examples: &[EvalExample]) {
+    let (a, b) = (&runs[0], &runs[1]);
+    let (sa, sb) = (summarize(cfg, a, examples), summarize(cfg, b, examples));
+    println!();
+    println!("== 对比 ==");
+    println!("  {:<12} {:>12} {:>12}", "", "A", "B");
+    println!("  {:<12} {:>12} {:>12}", "model", a.cfg.model, b.cfg.model);
+    let rows: [(&str, f64, f64); 3] = [
+        (
+            "precision",
+            sa.confusion.precision(),
+            sb.confusion.precision(),
+        ),
+        ("recall", sa.confusion.recall(), sb.confusion.recall()),
+        ("f1", sa.confusion.f1(), sb.confusion.f1()),
+    ];
+    for (name, x, y) in rows {
+        println!("  {:<12} {:>12.3} {:>12.3}", name, x, y);
+    }
+    println!("  {:<12} {:>12} {:>12}", "errors", sa.errors, sb.errors);
+    println!(
+        "  {:<12} {:>12} {:>12}",
+        "cost",
+        sa.cost.map(|c| format!("${:.4}", c)).unwrap_or("-".into()),
+        sb.cost.map(|c| format!("${:.4}", c)).unwrap_or("-".into()),
+    );
+    println!(
+        "  {:<12} {:>10}ms {:>10}ms",
+        "latency",
+        sa.latency_mean.as_millis(),
+        sb.latency_mean.as_millis()
+    );
+
+    let disagreements = a
+        .outcomes
+        .iter()
+        .zip(&b.outcomes)
+        .filter(|(x, y)| match (&x.result, &y.result) {
+            (Ok(x), Ok(y)) => {
+                (x.verdict.is_ad && x.verdict.confidence >= a.cfg.threshold)
+                    != (y.verdict.is_ad && y.verdict.confidence >= b.cfg.threshold)
+            }
+            _ => false,
+        })
+        .count();
+    println!("  A/B 判定不一致: {}", disagreements);
+}
+
+fn write_misclassified(path: &Path, runs: &[EvalRun], examples: &[EvalExample]) -> Result<()> {
+    #[derive(Serialize)]
+    struct Miss<'a> {
+        classifier: &'a str,
+        id: Option<&'a str>,
+        title: Option<&'a str>,
+        expected: bool,
+        predicted: bool,
+        confidence: f32,
+        reason: &'a str,
+        text: &'a str,
+    }
+
+    let mut out = String::new();
+    let mut count = 0usize;
+    for run in runs {
+        for o in &run.outcomes {
+            let Ok(res) = &o.result else { continue };
+            let ex = &examples[o.example];
+            let predicted = res.verdict.is_ad && res.verdict.confidence >= run.cfg.threshold;
+            if predicted == ex.is_ad {
+                continue;
+            }
+            out.push_str(&serde_json::to_string(&Miss {
+                classifier: &run.name,
+                id: ex.id.as_deref(),
+                title: ex.title.as_deref(),
+                expected: ex.is_ad,
+                predicted,
+                confidence: res.verdict.confidence,
+                reason: &res.verdict.reason,
+                text: &ex.text,
+            })?);
+            out.push('\n');
+            count += 1;
+        }
+    }
+    std::fs::write(path, out)
+        .with_context(|| format!("eval_misclassified_write_failed: {}", path.display()))?;
+    println!();
+    println!("误判样本 {} 条已写入 {}", count, path.display());
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use crate::config::FewShotConfig;
+use crate::db::{Database, LabeledReview};
+use crate::freshrss::FeverFeed;
+use crate::ratelimit::estimate_tokens;
+use crate::template::PromptVars;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+
+/// A labeled review sent ahead of the item as a user/assistant exchange.
+#[derive(Debug, Clone)]
+pub struct FewShotExample {
+    pub item_id: String,
+    pub vars: PromptVars,
+    pub is_ad: bool,
+}
+
+/// Reviews with feedback, loaded once per run, from which examples are picked per item.
+pub struct FewShotPool {
+    cfg: FewShotConfig,
+    candidates: Vec<Candidate>,
+}
+
+struct Candidate {
+    review: LabeledReview,
+    body: String,
+    tokens: HashSet<String>,
+    cost: u64,
+}
+
+impl FewShotPool {
+    pub async fn load(cfg: &FewShotConfig, db: &Database) -> Result<Self> {
+        let candidates: Vec<Candidate> = db
+            .labeled_reviews()
+            .await?
+            .into_iter()
+            .map(|review| {
+                let body = example_body(&review, cfg.max_example_chars);
+                let tokens = shingles(&review.text);
+                // Title, body and the assistant reply with some framing overhead.
+                let cost = estimate_tokens(&body)
+                    + estimate_tokens(review.title.as_deref().unwrap_or(""))
+                    + 40;
+                Candidate {
+                    review,
+                    body,
+                    tokens,
+                    cost,
+                }
+            })
+            .collect();
+        info!(
+            candidates = candidates.len(),
+            k = cfg.k,
+            "fewshot_pool_loaded"
+        );
+        Ok(Self {
+            cfg: cfg.clone(),
+            candidates,
+        })
+    }
+
+    /// Pick up to `k` examples for an item: same feed first, then by text similarity,
+    /// alternating ads and non-ads while the token budget allows. The most relevant
+    /// example comes last, right before the item.
+    pub fn select(
+        &self,
+        item_id: &str,
+        feed_id: Option<i64>,
+        text: &str,
+        feeds: &HashMap<i64, FeverFeed>,
+    ) -> Vec<FewShotExample> {
+        if self.cfg.k == 0 || self.candidates.is_empty() {
+            return Vec::new();
+        }
+        let tokens = shingles(text);
+        let mut ranked: Vec<(bool, f64, &Candidate)> = self
+            .candidates
+            .iter()
+            .filter(|c| c.review.item_id != item_id)
+            .map(|c| {
+                let same_feed = feed_id.is_some() && c.review.feed_id == feed_id;
+                (same_feed, jaccard(&tokens, &c.tokens), c)
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.total_cmp(&a.1)));
+
+        let (mut ads, mut others): (Vec<_>, Vec<_>) = ranked
+            .into_iter()
+            .map(|(_, _, c)| c)
+            .enumerate()
+            .partition(|(_, c)| c.review.is_ad);
+        ads.reverse();
+        others.reverse();
+
+        let mut picked: Vec<(usize, &Candidate)> = Vec::new();
+        let mut budget = self.cfg.max_tokens;
+        let mut want_ad = true;
+        while picked.len() < self.cfg.k {
+            let side = match (want_ad, ads.is_empty(), others.is_empty()) {
+                (_, true, true) => break,
+                (true, false, _) | (false, _, true) => &mut ads,
+                _ => &mut others,
+            };
+            let Some((rank, c)) = side.pop() else { break };
+            want_ad = !c.review.is_ad;
+            if c.cost <= budget {
+                budget -= c.cost;
+                picked.push((rank, c));
+            }
+        }
+        picked.sort_by_key(|(rank, _)| std::cmp::Reverse(*rank));
+
+        picked
+            .into_iter()
+            .map(|(_, c)| FewShotExample {
+                item_id: c.review.item_id.clone(),
+                vars: PromptVars {
+                    title: c.review.title.clone().unwrap_or_default(),
+                    feed_title: c
+                        .review
+                        .feed_id
+                        .and_then(|id| feeds.get(&id))
+                        .map(|f| f.title.clone()),
+                    text: c.body.clone(),
+                    ..Default::default()
+                },
+                is_ad: c.review.is_ad,
+            })
+            .collect()
+    }
+}
+
+/// Comma-separated ids, as stored with the review.
+pub fn example_ids(examples: &[FewShotExample]) -> Option<String> {
+    (!examples.is_empty()).then(|| {
+        examples
+            .iter()
+            .map(|e| e.item_id.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+}
+
+/// Stored review text without the leading title line, cut to `max_chars`.
+fn example_body(review: &LabeledReview, max_chars: usize) -> String {
+    let text = review
+        .title
+        .as_deref()
+        .and_then(|t| review.text.strip_prefix(t))
+        .unwrap_or(&review.text)
+        .trim();
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    text.chars().take(max_chars).collect::<String>() + "…"
+}
+
+/// Lowercased words for alphabetic scripts and character bigrams for scripts
+/// written without spaces, so Chinese text can be compared too.
+fn shingles(text: &str) -> HashSet<String> {
+    let mut out = HashSet::new();
+    let mut word = String::new();
+    let mut prev_wide: Option<char> = None;
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+            prev_wide = None;
+            continue;
+        }
+        if word.chars().count() >= 2 {
+            out.insert(std::mem::take(&mut word));
+        }
+        word.clear();
+        if c.is_alphabetic() {
+            if let Some(p) = prev_wide {
+                out.insert(format!("{}{}", p, c));
+            }
+            prev_wide = Some(c);
+        } else {
+            prev_wide = None;
+        }
+    }
+    if word.chars().count() >= 2 {
+        out.insert(word);
+    }
+    out
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.intersection(b).count();
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
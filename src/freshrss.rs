@@ -1,8 +1,9 @@
 use crate::config::FreshRssConfig;
+use crate::html::{self, HtmlContent};
 use anyhow::{Result, anyhow};
-use regex::Regex;
 use reqwest::{Client, Url};
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use tracing::instrument;
 
 #[derive(Clone)]
@@ -22,6 +23,13 @@ pub fn build_client(cfg: &FreshRssConfig) -> Result<FreshRssClient> {
     })
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeverFeed {
+    #[serde(deserialize_with = "de_i64_from_str_or_int")]
+    pub id: i64,
+    pub title: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct FeverItemsResp {
     pub items: Vec<FeverItem>,
@@ -31,6 +39,8 @@ pub struct FeverItemsResp {
 pub struct FeverItem {
     #[serde(deserialize_with = "de_i64_from_str_or_int")]
     pub id: i64,
+    #[serde(default, deserialize_with = "de_opt_i64_from_str_or_int")]
+    pub feed_id: Option<i64>,
     pub title: String,
     pub url: Option<String>,
     pub author: Option<String>,
@@ -185,6 +195,27 @@ impl FreshRssClient {
         Ok(items)
     }
 
+    /// Subscribed feeds by id.
+    #[instrument(name = "Fetching feeds", skip(self))]
+    pub async fn get_feeds(&self) -> Result<HashMap<i64, FeverFeed>> {
+        let url = self.fever_url_with("feeds")?;
+        let resp = self
+            .client
+            .post(url)
+            .form(&[("api_key", &self.fever_api_key)])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("fever_feeds_error: {}", resp.status()));
+        }
+        let v = resp.json::<serde_json::Value>().await?;
+        let feeds: Vec<FeverFeed> = v
+            .get("feeds")
+            .and_then(|f| serde_json::from_value(f.clone()).ok())
+            .unwrap_or_default();
+        Ok(feeds.into_iter().map(|f| (f.id, f)).collect())
+    }
+
     pub async fn mark_item_read(&self, item_id: i64) -> Result<()> {
         let url = self.fever_url_with(&format!("mark=item&as=read&id={}", item_id))?;
         let resp = self
@@ -199,29 +230,89 @@ impl FreshRssClient {
         Ok(())
     }
 
+    /// Save (star) an item.
+    pub async fn mark_item_saved(&self, item_id: i64) -> Result<()> {
+        let url = self.fever_url_with(&format!("mark=item&as=saved&id={}", item_id))?;
+        let resp = self
+            .client
+            .post(url)
+            .form(&[("api_key", &self.fever_api_key)])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("mark_saved_error: {}", resp.status()));
+        }
+        Ok(())
+    }
+
+    pub async fn mark_item_unread(&self, item_id: i64) -> Result<()> {
+        let url = self.fever_url_with(&format!("mark=item&as=unread&id={}", item_id))?;
+        let resp = self
+            .client
+            .post(url)
+            .form(&[("api_key", &self.fever_api_key)])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("mark_unread_error: {}", resp.status()));
+        }
+        Ok(())
+    }
+
     pub async fn delete_item_soft(&self, item_id: i64) -> Result<()> {
         // FreshRSS Fever API has mark as read; real deletion requires admin API.
         self.mark_item_read(item_id).await
     }
 }
 
+/// Title, author and body, as stored with the review and hashed.
 pub fn item_text(item: &FeverItem) -> String {
     let mut text = String::new();
     text.push_str(&item.title);
     if let Some(a) = &item.author {
         text.push_str(&format!("\nby {}", a));
     }
-    if let Some(c) = &item.content {
-        text.push_str(&format!("\n{}", c));
-    }
-    if let Some(h) = &item.html {
-        text.push_str(&format!("\n{}", strip_html(h)));
+    let body = item_body(item, &item_html(item));
+    if !body.is_empty() {
+        text.push_str(&format!("\n{}", body));
     }
     text
 }
 
-fn strip_html(html: &str) -> String {
-    // basic strip for classifier context
-    let re = Regex::new(r"<[^>]+>").unwrap();
-    re.replace_all(html, " ").to_string()
+/// The body of text built by `item_text`, without its title and author lines.
+pub fn stored_body<'a>(title: Option<&str>, text: &'a str) -> &'a str {
+    let Some(rest) = title.and_then(|t| text.strip_prefix(t)) else {
+        return text.trim();
+    };
+    let rest = match rest.strip_prefix("\nby ") {
+        Some(author_line) => author_line.split_once('\n').map_or("", |(_, body)| body),
+        None => rest,
+    };
+    rest.trim()
+}
+
+/// Parsed item HTML: readable text, links, image alts and iframes.
+pub fn item_html(item: &FeverItem) -> HtmlContent {
+    item.html.as_deref().map(html::extract).unwrap_or_default()
+}
+
+/// Content and HTML text without the title and author lines. Feeds often carry the
+/// same article in both, so when one contains the other only the longer is kept.
+pub fn item_body(item: &FeverItem, html: &HtmlContent) -> String {
+    let content = item.content.as_deref().unwrap_or("").trim();
+    let (c, h) = (normalize(content), normalize(&html.text));
+    if c.is_empty() || h.contains(&c) {
+        return html.text.clone();
+    }
+    if h.is_empty() || c.contains(&h) {
+        return content.to_string();
+    }
+    format!("{}\n{}", content, html.text)
+}
+
+fn normalize(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
 }
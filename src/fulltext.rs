@@ -0,0 +1,271 @@
+use crate::config::FullTextConfig;
+use crate::db::Database;
+use crate::freshrss::FeverItem;
+use crate::links::matches_domain;
+use crate::resolver::{PublicDns, ensure_public};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url, header};
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Failed fetches are tried again after this long.
+const RETRY_FAILED_HOURS: i64 = 24;
+
+/// Articles extracted with less text than this are treated as failures.
+const MIN_ARTICLE_CHARS: usize = 200;
+
+/// Elements never part of the article.
+const UNLIKELY_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+    "button", "svg",
+];
+
+/// Class and id words of containers that usually hold the article.
+const POSITIVE: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "text", "blog", "story",
+];
+
+/// Class and id words of page furniture. Sponsor boxes are deliberately not listed:
+/// they are what the classifier needs to see.
+const NEGATIVE: &[&str] = &[
+    "comment",
+    "meta",
+    "footer",
+    "footnote",
+    "sidebar",
+    "widget",
+    "menu",
+    "share",
+    "social",
+    "related",
+    "masthead",
+    "breadcrumb",
+    "popup",
+    "cookie",
+];
+
+/// Downloads the linked page of teaser-only items and keeps the main article.
+pub struct FullText {
+    cfg: FullTextConfig,
+    http: Client,
+    db: Database,
+}
+
+impl FullText {
+    pub fn new(cfg: &FullTextConfig, user_agent: &str, db: Database) -> Result<Self> {
+        let http = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= 5 {
+                    attempt.error("fulltext_too_many_redirects")
+                } else if let Err(e) = ensure_public(attempt.url()) {
+                    attempt.error(e)
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .dns_resolver(Arc::new(PublicDns))
+            .build()?;
+        Ok(Self {
+            cfg: cfg.clone(),
+            http,
+            db,
+        })
+    }
+
+    /// The URL to fetch for an item whose body has `body_chars` characters, if the
+    /// body is short and the feed and domain are allowed.
+    pub fn wants(&self, item: &FeverItem, body_chars: usize) -> Option<Url> {
+        if body_chars >= self.cfg.min_chars {
+            return None;
+        }
+        if let Some(feed) = item.feed_id
+            && (self.cfg.skip_feeds.contains(&feed)
+                || (!self.cfg.feeds.is_empty() && !self.cfg.feeds.contains(&feed)))
+        {
+            return None;
+        }
+        if item.feed_id.is_none() && !self.cfg.feeds.is_empty() {
+            return None;
+        }
+        let url = Url::parse(item.url.as_deref()?).ok()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+        let host = url.host_str()?.to_lowercase();
+        if matches_domain(&host, &self.cfg.deny_domains)
+            || (!self.cfg.allow_domains.is_empty()
+                && !matches_domain(&host, &self.cfg.allow_domains))
+        {
+            return None;
+        }
+        Some(url)
+    }
+
+    /// Article HTML for `url`, from the cache or freshly fetched and extracted.
+    /// Failures are cached too and only retried after a while.
+    pub async fn article(&self, url: &Url) -> Option<String> {
+        let key = url.as_str();
+        match self.db.fulltext_cached(key).await {
+            Ok(Some(cached)) => {
+                if let Some(html) = cached.html {
+                    return Some(html);
+                }
+                let retry_after = DateTime::parse_from_rfc3339(&cached.fetched_at)
+                    .map(|t| t.with_timezone(&Utc) + chrono::Duration::hours(RETRY_FAILED_HOURS))
+                    .unwrap_or_default();
+                if Utc::now() < retry_after {
+                    debug!(url = key, error = ?cached.error, "fulltext_cached_failure");
+                    return None;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!(url = key, error = %e, "fulltext_cache_read_failed"),
+        }
+        let fetched = self
+            .fetch(url)
+            .await
+            .and_then(|page| extract_article(&page).ok_or_else(|| anyhow!("fulltext_no_article")));
+        let (html, error) = match &fetched {
+            Ok(html) => {
+                debug!(url = key, bytes = html.len(), "fulltext_fetched");
+                (Some(html.as_str()), None)
+            }
+            Err(e) => {
+                warn!(url = key, error = %e, "fulltext_fetch_failed");
+                (None, Some(e.to_string()))
+            }
+        };
+        if let Err(e) = self.db.save_fulltext(key, html, error.as_deref()).await {
+            warn!(url = key, error = %e, "fulltext_cache_save_failed");
+        }
+        fetched.ok()
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<String> {
+        ensure_public(url)?;
+        let mut resp = self
+            .http
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_lowercase();
+        if !content_type.is_empty() && !content_type.contains("html") {
+            return Err(anyhow!("fulltext_not_html: {}", content_type));
+        }
+        if resp
+            .content_length()
+            .is_some_and(|n| n > self.cfg.max_bytes as u64)
+        {
+            return Err(anyhow!(
+                "fulltext_too_large: > {} bytes",
+                self.cfg.max_bytes
+            ));
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > self.cfg.max_bytes {
+                return Err(anyhow!(
+                    "fulltext_too_large: > {} bytes",
+                    self.cfg.max_bytes
+                ));
+            }
+        }
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// HTML of the element most likely to hold the article, readability style: paragraphs
+/// score their parent and grandparent by length and commas, containers are weighted by
+/// class and id words and penalised by link density.
+pub fn extract_article(page: &str) -> Option<String> {
+    let doc = Html::parse_document(page);
+    let paragraphs = Selector::parse("p, pre, blockquote, td").expect("valid selector");
+    let mut scores = HashMap::new();
+    for p in doc.select(&paragraphs) {
+        if p.ancestors().filter_map(ElementRef::wrap).any(is_unlikely) {
+            continue;
+        }
+        let text = collapse(&p.text().collect::<String>());
+        let chars = text.chars().count();
+        if chars < 25 {
+            continue;
+        }
+        let commas = text.matches([',', '，', '、']).count();
+        let score = 1.0 + commas as f64 + (chars as f64 / 100.0).min(3.0);
+        let mut ancestors = p.ancestors().filter_map(ElementRef::wrap);
+        for (share, el) in [1.0, 0.5].into_iter().zip(ancestors.by_ref()) {
+            *scores.entry(el.id()).or_insert_with(|| class_weight(el)) += score * share;
+        }
+    }
+    let best = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let el = ElementRef::wrap(doc.tree.get(id)?)?;
+            Some((el, score * (1.0 - link_density(el))))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(el, _)| el)
+        .or_else(|| {
+            let fallback = Selector::parse("article, main").expect("valid selector");
+            doc.select(&fallback).next()
+        })?;
+    let html = best.html();
+    (crate::html::extract(&html).text.chars().count() >= MIN_ARTICLE_CHARS).then_some(html)
+}
+
+fn is_unlikely(el: ElementRef) -> bool {
+    UNLIKELY_TAGS.contains(&el.value().name())
+}
+
+fn class_weight(el: ElementRef) -> f64 {
+    let names = format!(
+        "{} {}",
+        el.value().attr("class").unwrap_or(""),
+        el.value().id().unwrap_or("")
+    )
+    .to_lowercase();
+    let mut weight = match el.value().name() {
+        "article" => 10.0,
+        "main" | "section" | "div" => 5.0,
+        _ => 0.0,
+    };
+    if POSITIVE.iter().any(|w| names.contains(w)) {
+        weight += 25.0;
+    }
+    if NEGATIVE.iter().any(|w| names.contains(w)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+/// Share of the element's text that sits inside links.
+fn link_density(el: ElementRef) -> f64 {
+    let total = el.text().map(|t| t.trim().chars().count()).sum::<usize>();
+    if total == 0 {
+        return 1.0;
+    }
+    let links = Selector::parse("a").expect("valid selector");
+    let linked = el
+        .select(&links)
+        .flat_map(|a| a.text())
+        .map(|t| t.trim().chars().count())
+        .sum::<usize>();
+    linked as f64 / total as f64
+}
+
+fn collapse(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -1,6 +1,27 @@
 use crate::config::FreshRssConfig;
 use anyhow::{Result, anyhow};
 use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// GReader state tag of starred items.
+const STARRED: &str = "user/-/state/com.google/starred";
+
+/// Item ids requested per page of a stream.
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Deserialize)]
+struct ItemIds {
+    #[serde(default, rename = "itemRefs")]
+    item_refs: Vec<ItemRef>,
+    #[serde(default)]
+    continuation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ItemRef {
+    id: String,
+}
 
 #[derive(Clone)]
 pub struct GReaderClient {
@@ -27,13 +48,64 @@ pub fn build_client(
 
 impl GReaderClient {
     pub async fn add_label(&self, item_id: i64, label: &str) -> Result<()> {
+        self.edit_tag(item_id, "a", &format!("user/-/label/{}", label))
+            .await
+    }
+
+    pub async fn remove_label(&self, item_id: i64, label: &str) -> Result<()> {
+        self.edit_tag(item_id, "r", &format!("user/-/label/{}", label))
+            .await
+    }
+
+    pub async fn star(&self, item_id: i64) -> Result<()> {
+        self.edit_tag(item_id, "a", STARRED).await
+    }
+
+    /// Ids of every item currently carrying `label`.
+    pub async fn labeled_item_ids(&self, label: &str) -> Result<HashSet<i64>> {
+        let url = self
+            .base
+            .join("/api/greader.php/reader/api/0/stream/items/ids")?;
+        let stream = format!("user/-/label/{}", label);
+        let mut ids = HashSet::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("s", stream.clone()), ("n", PAGE_SIZE.to_string())];
+            if let Some(c) = &continuation {
+                query.push(("c", c.clone()));
+            }
+            let resp = self
+                .client
+                .get(url.clone())
+                .basic_auth(&self.username, Some(&self.password))
+                .query(&query)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(anyhow!("greader_stream_ids_error: {}", resp.status()));
+            }
+            let page: ItemIds = resp.json().await?;
+            // FreshRSS sends decimal ids here; the long hex form is not expected.
+            ids.extend(
+                page.item_refs
+                    .iter()
+                    .filter_map(|r| r.id.parse::<i64>().ok()),
+            );
+            match page.continuation.filter(|c| !c.is_empty()) {
+                Some(c) if !page.item_refs.is_empty() => continuation = Some(c),
+                _ => return Ok(ids),
+            }
+        }
+    }
+
+    /// `op` is `a` to add the tag or `r` to remove it.
+    async fn edit_tag(&self, item_id: i64, op: &str, tag: &str) -> Result<()> {
         let url = self.base.join("/api/greader.php/reader/api/0/edit-tag")?;
-        let tag = format!("user/-/label/{}", label);
         let resp = self
             .client
             .post(url)
             .basic_auth(&self.username, Some(&self.password))
-            .form(&[("i", item_id.to_string()), ("a", tag)])
+            .form(&[("i", item_id.to_string()), (op, tag.to_string())])
             .send()
             .await?;
         if !resp.status().is_success() {
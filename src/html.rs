@@ -0,0 +1,179 @@
+use scraper::{ElementRef, Html, Node};
+
+/// Upper bounds for the side channel so link farms do not flood the prompt.
+const MAX_LINKS: usize = 20;
+const MAX_IMAGES: usize = 20;
+const MAX_IFRAMES: usize = 10;
+
+/// Elements whose content is never shown to readers.
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "template", "head", "svg", "math",
+];
+
+/// Elements rendered on their own line.
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// A hyperlink with its visible text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub text: String,
+    pub href: String,
+}
+
+/// Readable text of an HTML fragment plus the parts a tag stripper loses.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlContent {
+    /// Entity-decoded text with one line per block element
+    pub text: String,
+    /// Distinct link targets in document order
+    pub links: Vec<Link>,
+    /// Non-empty `alt` texts of images
+    pub image_alts: Vec<String>,
+    /// Distinct image `src` values
+    pub image_srcs: Vec<String>,
+    /// `src` of images declared at most 2x2 pixels, the usual tracking pixel
+    pub pixels: Vec<String>,
+    /// `src` of embedded iframes
+    pub iframes: Vec<String>,
+}
+
+/// Parse an HTML fragment, dropping scripts and styles, decoding entities and
+/// collapsing whitespace while keeping block structure.
+pub fn extract(html: &str) -> HtmlContent {
+    let doc = Html::parse_fragment(html);
+    let mut out = HtmlContent::default();
+    let mut raw = String::new();
+    walk(doc.root_element(), &mut raw, &mut out);
+    out.text = collapse_whitespace(&raw);
+    out
+}
+
+fn walk(el: ElementRef, raw: &mut String, out: &mut HtmlContent) {
+    for child in el.children() {
+        match child.value() {
+            Node::Text(t) => raw.push_str(t),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    visit(child, raw, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn visit(el: ElementRef, raw: &mut String, out: &mut HtmlContent) {
+    let name = el.value().name();
+    if SKIPPED.contains(&name) {
+        return;
+    }
+    match name {
+        "a" => {
+            if let Some(href) = attr(el, "href")
+                && !href.starts_with('#')
+                && !href.starts_with("javascript:")
+                && out.links.len() < MAX_LINKS
+                && !out.links.iter().any(|l| l.href == href)
+            {
+                let text = collapse_whitespace(&el.text().collect::<String>()).replace('\n', " ");
+                out.links.push(Link { text, href });
+            }
+        }
+        "img" => {
+            if let Some(src) = attr(el, "src") {
+                let tiny = ["width", "height"].iter().all(|d| {
+                    attr(el, d)
+                        .and_then(|v| v.trim_end_matches("px").parse::<u32>().ok())
+                        .is_some_and(|v| v <= 2)
+                });
+                if tiny && !out.pixels.contains(&src) {
+                    out.pixels.push(src.clone());
+                }
+                if out.image_srcs.len() < MAX_IMAGES && !out.image_srcs.contains(&src) {
+                    out.image_srcs.push(src);
+                }
+            }
+            if let Some(alt) = attr(el, "alt")
+                && out.image_alts.len() < MAX_IMAGES
+                && !out.image_alts.contains(&alt)
+            {
+                // Alt text stands in for the image in the reading flow too.
+                raw.push(' ');
+                raw.push_str(&alt);
+                raw.push(' ');
+                out.image_alts.push(alt);
+            }
+        }
+        "iframe" => {
+            if let Some(src) = attr(el, "src")
+                && out.iframes.len() < MAX_IFRAMES
+                && !out.iframes.contains(&src)
+            {
+                out.iframes.push(src);
+            }
+            return;
+        }
+        _ => {}
+    }
+    let block = BLOCKS.contains(&name);
+    if block {
+        raw.push('\n');
+    }
+    walk(el, raw, out);
+    if block {
+        raw.push('\n');
+    }
+}
+
+/// Trimmed, non-empty attribute value.
+fn attr(el: ElementRef, name: &str) -> Option<String> {
+    el.value()
+        .attr(name)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Collapse runs of whitespace within lines and drop empty lines.
+fn collapse_whitespace(raw: &str) -> String {
+    raw.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,146 @@
+use crate::config::ImagesConfig;
+use crate::freshrss::FeverItem;
+use crate::html::HtmlContent;
+use crate::links::resolve;
+use crate::resolver::{PublicDns, ensure_public};
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use reqwest::{Client, Url, header};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// An image sent to the model next to the item text.
+#[derive(Debug, Clone)]
+pub struct ImageInput {
+    /// `data:` URL of the downscaled image
+    pub url: String,
+    /// `detail` requested from the model
+    pub detail: String,
+}
+
+/// Downloads the lead images of an item and prepares them as image inputs.
+pub struct ImageFetcher {
+    cfg: ImagesConfig,
+    http: Client,
+}
+
+impl ImageFetcher {
+    pub fn new(cfg: &ImagesConfig, user_agent: &str) -> Result<Self> {
+        let http = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= 3 {
+                    attempt.error("image_too_many_redirects")
+                } else if ensure_public(attempt.url()).is_err() {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .dns_resolver(Arc::new(PublicDns))
+            .build()?;
+        Ok(Self {
+            cfg: cfg.clone(),
+            http,
+        })
+    }
+
+    /// Whether the item's feed sends images.
+    pub fn wants(&self, item: &FeverItem) -> bool {
+        match item.feed_id {
+            Some(feed) => {
+                !self.cfg.skip_feeds.contains(&feed)
+                    && (self.cfg.feeds.is_empty() || self.cfg.feeds.contains(&feed))
+            }
+            None => self.cfg.feeds.is_empty(),
+        }
+    }
+
+    /// Images of the item in document order, tracking pixels left out.
+    pub fn candidates(&self, html: &HtmlContent, base: Option<&str>) -> Vec<Url> {
+        let base = base.and_then(|b| Url::parse(b).ok());
+        html.image_srcs
+            .iter()
+            .filter(|src| !html.pixels.contains(src))
+            .filter_map(|src| resolve(src, base.as_ref()))
+            .collect()
+    }
+
+    /// Up to `max_images` of `urls`, downscaled to JPEG. Images that fail to download
+    /// or decode, or are too small to matter, are skipped.
+    pub async fn load(&self, urls: &[Url]) -> Vec<ImageInput> {
+        let mut out = Vec::new();
+        for url in urls {
+            if out.len() >= self.cfg.max_images {
+                break;
+            }
+            match self.load_one(url).await {
+                Ok(Some(url)) => out.push(ImageInput {
+                    url,
+                    detail: self.cfg.detail.clone(),
+                }),
+                Ok(None) => debug!(url = %url, "image_too_small"),
+                Err(e) => warn!(url = %url, error = %e, "image_load_failed"),
+            }
+        }
+        out
+    }
+
+    async fn load_one(&self, url: &Url) -> Result<Option<String>> {
+        ensure_public(url)?;
+        let mut resp = self
+            .http
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_lowercase();
+        if !content_type.is_empty() && !content_type.starts_with("image/") {
+            return Err(anyhow!("image_not_image: {}", content_type));
+        }
+        if resp
+            .content_length()
+            .is_some_and(|n| n > self.cfg.max_bytes as u64)
+        {
+            return Err(anyhow!("image_too_large: > {} bytes", self.cfg.max_bytes));
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > self.cfg.max_bytes {
+                return Err(anyhow!("image_too_large: > {} bytes", self.cfg.max_bytes));
+            }
+        }
+        let cfg = self.cfg.clone();
+        tokio::task::spawn_blocking(move || downscale(&bytes, &cfg)).await?
+    }
+}
+
+/// Decode, shrink to fit `max_dimension` and re-encode as a JPEG data URL.
+/// `None` for images smaller than `min_dimension` on either side.
+fn downscale(bytes: &[u8], cfg: &ImagesConfig) -> Result<Option<String>> {
+    let img = image::load_from_memory(bytes)?;
+    if img.width().min(img.height()) < cfg.min_dimension {
+        return Ok(None);
+    }
+    let img = if img.width().max(img.height()) > cfg.max_dimension {
+        img.thumbnail(cfg.max_dimension, cfg.max_dimension)
+    } else {
+        img
+    };
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, cfg.quality.clamp(1, 100))
+        .encode_image(&img.to_rgb8())?;
+    Ok(Some(format!(
+        "data:image/jpeg;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(&jpeg)
+    )))
+}
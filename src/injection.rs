@@ -0,0 +1,166 @@
+use crate::config::InjectionConfig;
+use crate::openai_client::Classification;
+use crate::template::PromptVars;
+
+/// Imperative phrases that try to override the classifier's instructions, lowercase.
+/// Kept to multi-word commands: topic words like "system prompt" show up in ordinary tech posts.
+const INSTRUCTION_PATTERNS: &[&str] = &[
+    "ignore previous instructions",
+    "ignore all previous",
+    "ignore the previous",
+    "ignore prior instructions",
+    "ignore all prior",
+    "ignore the above",
+    "ignore your instructions",
+    "disregard previous",
+    "disregard all previous",
+    "disregard the above",
+    "disregard your instructions",
+    "forget your instructions",
+    "forget previous instructions",
+    "override your instructions",
+    "new instructions:",
+    "忽略之前",
+    "忽略以上",
+    "忽略上述",
+    "忽略前面",
+    "忽略所有指令",
+    "无视之前",
+    "无视以上",
+    "忘记之前的指令",
+    "新的指令：",
+];
+
+/// Special tokens of common chat templates, lowercase.
+const ROLE_MARKERS: &[&str] = &[
+    "<|im_start|>",
+    "<|im_end|>",
+    "<|system|>",
+    "<|user|>",
+    "<|assistant|>",
+    "<|endoftext|>",
+    "<|eot_id|>",
+    "<|start_header_id|>",
+    "[inst]",
+    "[/inst]",
+    "<<sys>>",
+];
+
+/// Line starts that imitate a chat transcript, lowercase.
+const ROLE_PREFIXES: &[&str] = &[
+    "system:",
+    "assistant:",
+    "### system",
+    "### instruction",
+    "### assistant",
+];
+
+/// Replaces sentences dropped from the text sent to the secondary check.
+const REDACTED: &str = "[instruction-like text removed]";
+
+/// Instruction-like phrases and role markers found in feed content.
+pub struct Detector {
+    patterns: Vec<String>,
+}
+
+impl Detector {
+    pub fn new(cfg: &InjectionConfig) -> Self {
+        let patterns = INSTRUCTION_PATTERNS
+            .iter()
+            .chain(ROLE_MARKERS)
+            .map(|p| p.to_string())
+            .chain(cfg.patterns.iter().map(|p| normalize(p)))
+            .filter(|p| !p.is_empty())
+            .collect();
+        Self { patterns }
+    }
+
+    /// Patterns found in the title, author, text, link texts and image alts of an item.
+    pub fn detect(&self, vars: &PromptVars) -> Vec<String> {
+        let mut found: Vec<String> = Vec::new();
+        let fields = [vars.title.as_str(), vars.author.as_deref().unwrap_or("")]
+            .into_iter()
+            .chain(vars.text.lines())
+            .chain(vars.links.iter().map(|l| l.text.as_str()))
+            .chain(vars.images.iter().map(String::as_str));
+        for field in fields {
+            for hit in self.matches(field) {
+                if !found.contains(&hit) {
+                    found.push(hit);
+                }
+            }
+        }
+        found
+    }
+
+    /// Copy of the item with every sentence carrying a pattern replaced by a placeholder.
+    pub fn redact(&self, vars: &PromptVars) -> PromptVars {
+        let clean = |s: &str| {
+            s.lines()
+                .map(|line| {
+                    line.split_inclusive(['.', '!', '?', '。', '！', '？'])
+                        .map(|sentence| {
+                            if self.matches(sentence).is_empty() {
+                                sentence
+                            } else {
+                                REDACTED
+                            }
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        PromptVars {
+            title: clean(&vars.title),
+            text: clean(&vars.text),
+            ..vars.clone()
+        }
+    }
+
+    fn matches(&self, s: &str) -> Vec<String> {
+        let s = normalize(s);
+        let mut hits: Vec<String> = self
+            .patterns
+            .iter()
+            .filter(|p| s.contains(p.as_str()))
+            .cloned()
+            .collect();
+        if let Some(prefix) = ROLE_PREFIXES.iter().find(|p| s.starts_with(*p)) {
+            hits.push(prefix.to_string());
+        }
+        hits
+    }
+}
+
+/// The verdict leaning towards ad of the original and the redacted classification:
+/// an injection only ever pushes towards not-ad. Usage covers both calls.
+pub fn stricter(first: Classification, second: Classification) -> Classification {
+    let mut usage = first.usage;
+    usage.add(&second.usage);
+    let second_wins = second.verdict.is_ad
+        && (!first.verdict.is_ad || second.verdict.confidence > first.verdict.confidence);
+    let mut best = if second_wins {
+        let mut second = second;
+        second.verdict.reason = format!("injection_check: {}", second.verdict.reason);
+        second
+    } else {
+        first
+    };
+    best.usage = usage;
+    best
+}
+
+/// Escape angle brackets so content cannot close the `<item>` block of the prompt
+/// or pass for a chat template token.
+pub fn escape(s: &str) -> String {
+    s.replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Lowercase with whitespace runs collapsed, so spacing tricks do not hide a phrase.
+fn normalize(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,298 @@
+use crate::config::InputConfig;
+use crate::openai_client::{Classification, FilterVerdict, OpenAiApiError, TokenUsage};
+use crate::ratelimit::{estimate_tokens, is_cjk};
+use crate::verdict::VerdictParse;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Marker placed where the middle of a truncated article was cut out.
+const ELISION: &str = "\n[…]\n";
+
+/// Item text fitted to the input budget.
+#[derive(Debug, Clone)]
+pub enum Prepared {
+    /// Fits as is
+    Whole(String),
+    /// Head and tail kept, middle cut
+    Truncated(String),
+    /// Classified chunk by chunk, verdicts combined afterwards
+    Chunks(Vec<String>),
+}
+
+/// The item is longer than `max_tokens * max_chunks`, or the provider rejected it as
+/// exceeding the context window. It is counted and not classified.
+#[derive(Debug)]
+pub struct OversizedError {
+    pub tokens: u64,
+}
+
+impl fmt::Display for OversizedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "item_oversized: ~{} tokens", self.tokens)
+    }
+}
+
+impl std::error::Error for OversizedError {}
+
+/// Fit `text` to the budget: whole when it fits, head and tail up to `chunk_above`,
+/// chunks up to `max_chunks`, oversized beyond that.
+pub fn prepare(cfg: &InputConfig, text: &str) -> Result<Prepared, OversizedError> {
+    let tokens = estimate_tokens(text);
+    let budget = budget(cfg);
+    if cfg.max_tokens == 0 || tokens <= budget {
+        return Ok(Prepared::Whole(text.to_string()));
+    }
+    if cfg.chunk_above == 0 || tokens <= cfg.chunk_above {
+        return Ok(Prepared::Truncated(head_and_tail(
+            text,
+            budget,
+            cfg.head_ratio,
+        )));
+    }
+    let chunks = chunk(text, budget);
+    if chunks.len() > cfg.max_chunks {
+        return Err(OversizedError { tokens });
+    }
+    Ok(Prepared::Chunks(chunks))
+}
+
+/// `max_tokens` less the safety margin. Estimates run low on URL- and code-heavy text,
+/// where tokens are shorter than the four characters assumed.
+fn budget(cfg: &InputConfig) -> u64 {
+    let keep = 1.0 - cfg.safety_margin.clamp(0.0, 0.9) as f64;
+    ((cfg.max_tokens as f64 * keep).round() as u64).max(1)
+}
+
+/// Whether a provider error says the request exceeded the model's context window.
+pub fn is_context_overflow(err: &OpenAiApiError) -> bool {
+    err.code() == Some("context_length_exceeded")
+        || err
+            .body
+            .to_string()
+            .to_lowercase()
+            .contains("maximum context length")
+}
+
+/// First `head_ratio` of the budget from the start, the rest from the end.
+fn head_and_tail(text: &str, budget: u64, head_ratio: f32) -> String {
+    let head_budget = (budget as f64 * head_ratio.clamp(0.0, 1.0) as f64) as u64;
+    let tail_budget = budget.saturating_sub(head_budget);
+    let head_end = prefix_len(text, head_budget);
+    let tail_start = text.len() - suffix_len(&text[head_end..], tail_budget);
+    format!(
+        "{}{}{}",
+        text[..head_end].trim_end(),
+        ELISION,
+        text[tail_start..].trim_start()
+    )
+}
+
+/// Split into pieces of at most `budget` tokens, preferring line boundaries.
+fn chunk(text: &str, budget: u64) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    for line in text.lines() {
+        let mut line = line;
+        loop {
+            let tokens = estimate_tokens(line) + 1;
+            if current_tokens + tokens <= budget {
+                current.push_str(line);
+                current.push('\n');
+                current_tokens += tokens;
+                break;
+            }
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                current_tokens = 0;
+                continue;
+            }
+            // A single line longer than the budget is cut wherever the budget ends.
+            let cut = prefix_len(line, budget).max(line.chars().next().map_or(0, char::len_utf8));
+            chunks.push(line[..cut].to_string());
+            line = &line[cut..];
+            if line.is_empty() {
+                break;
+            }
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Byte length of the longest prefix within `budget` estimated tokens.
+fn prefix_len(text: &str, budget: u64) -> usize {
+    let mut cost = 0.0;
+    for (i, c) in text.char_indices() {
+        cost += char_cost(c);
+        if cost > budget as f64 {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Byte length of the longest suffix within `budget` estimated tokens.
+fn suffix_len(text: &str, budget: u64) -> usize {
+    let mut cost = 0.0;
+    for (i, c) in text.char_indices().rev() {
+        cost += char_cost(c);
+        if cost > budget as f64 {
+            return text.len() - i - c.len_utf8();
+        }
+    }
+    text.len()
+}
+
+/// Per-character share of `estimate_tokens`.
+fn char_cost(c: char) -> f64 {
+    if is_cjk(c) { 1.0 } else { 0.25 }
+}
+
+/// Combine chunk verdicts with the rule used for replies carrying several verdicts:
+/// the most confident ad chunk wins, otherwise the most confident chunk.
+pub fn combine(parts: Vec<Classification>) -> Option<Classification> {
+    let total = parts.len();
+    let mut usage = TokenUsage::default();
+    for p in &parts {
+        usage.add(&p.usage);
+    }
+    let parse = parts
+        .iter()
+        .map(|p| p.parse)
+        .find(|p| *p != VerdictParse::Strict)
+        .unwrap_or(VerdictParse::Strict);
+    // Per filter, the strongest match of any part, else the most confident miss.
+    let mut filters: HashMap<String, FilterVerdict> = HashMap::new();
+    for (name, v) in parts.iter().flat_map(|p| &p.verdict.filters) {
+        let entry = filters.entry(name.clone()).or_insert(*v);
+        if (v.matched, v.confidence) > (entry.matched, entry.confidence) {
+            *entry = *v;
+        }
+    }
+    // The item is as interesting as its most interesting part.
+    let interest = parts
+        .iter()
+        .filter_map(|p| p.verdict.interest)
+        .max_by(f32::total_cmp);
+    let any_ad = parts.iter().any(|p| p.verdict.is_ad);
+    let (index, mut best) = parts
+        .into_iter()
+        .enumerate()
+        .filter(|(_, p)| p.verdict.is_ad == any_ad)
+        .max_by(|(_, a), (_, b)| a.verdict.confidence.total_cmp(&b.verdict.confidence))?;
+    best.verdict.reason = format!("chunk {}/{}: {}", index + 1, total, best.verdict.reason);
+    best.verdict.filters = filters;
+    best.verdict.interest = interest;
+    best.usage = usage;
+    best.parse = parse;
+    Some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(max_tokens: u64, chunk_above: u64) -> InputConfig {
+        InputConfig {
+            max_tokens,
+            chunk_above,
+            max_chunks: 4,
+            ..InputConfig::default()
+        }
+    }
+
+    #[test]
+    fn safety_margin_shrinks_the_budget() {
+        // 100 tokens with the default 20% margin leaves 80, i.e. 320 ASCII characters.
+        let cfg = cfg(100, 0);
+        assert!(matches!(
+            prepare(&cfg, &"a".repeat(320)),
+            Ok(Prepared::Whole(_))
+        ));
+        assert!(matches!(
+            prepare(&cfg, &"a".repeat(321)),
+            Ok(Prepared::Truncated(_))
+        ));
+        let exact = InputConfig {
+            safety_margin: 0.0,
+            ..cfg
+        };
+        assert!(matches!(
+            prepare(&exact, &"a".repeat(400)),
+            Ok(Prepared::Whole(_))
+        ));
+        assert!(matches!(
+            prepare(&exact, &"a".repeat(401)),
+            Ok(Prepared::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn truncation_keeps_head_and_tail_within_budget() {
+        let text = format!("{}{}{}", "h".repeat(400), "m".repeat(2000), "t".repeat(400));
+        let Ok(Prepared::Truncated(out)) = prepare(&cfg(100, 0), &text) else {
+            panic!("expected truncation");
+        };
+        let (head, tail) = out.split_once(ELISION).expect("elision marker");
+        assert_eq!(head, "h".repeat(220));
+        assert_eq!(tail, "t".repeat(100));
+        assert!(estimate_tokens(head) + estimate_tokens(tail) <= 80);
+    }
+
+    #[test]
+    fn cjk_characters_count_as_one_token_each() {
+        let cfg = InputConfig {
+            safety_margin: 0.0,
+            ..cfg(10, 0)
+        };
+        assert!(matches!(
+            prepare(&cfg, &"广".repeat(10)),
+            Ok(Prepared::Whole(_))
+        ));
+        let Ok(Prepared::Truncated(out)) = prepare(&cfg, &"广".repeat(11)) else {
+            panic!("expected truncation");
+        };
+        assert_eq!(out.matches('广').count(), 10);
+    }
+
+    #[test]
+    fn chunks_fill_up_to_the_budget() {
+        // Each line costs 10 tokens plus one for its newline; 80 tokens hold 7 lines.
+        let line = "x".repeat(40);
+        let text = vec![line.as_str(); 20].join("\n");
+        let Ok(Prepared::Chunks(chunks)) = prepare(&cfg(100, 100), &text) else {
+            panic!("expected chunks");
+        };
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].lines().count(), 7);
+        assert_eq!(chunks[2].lines().count(), 6);
+        assert!(chunks.iter().all(|c| estimate_tokens(c) <= 80));
+    }
+
+    #[test]
+    fn long_lines_are_cut_at_the_budget() {
+        let Ok(Prepared::Chunks(chunks)) = prepare(&cfg(100, 100), &"y".repeat(1000)) else {
+            panic!("expected chunks");
+        };
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|c| c.trim_end().len())
+                .collect::<Vec<_>>(),
+            [320, 320, 320, 40]
+        );
+    }
+
+    #[test]
+    fn too_many_chunks_is_oversized() {
+        let text = "z".repeat(320 * 4 + 1);
+        assert!(prepare(&cfg(100, 100), &text).is_err());
+        assert!(matches!(
+            prepare(&cfg(100, 100), &"z".repeat(320 * 4)),
+            Ok(Prepared::Chunks(_))
+        ));
+    }
+}
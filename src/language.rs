@@ -0,0 +1,39 @@
+use whatlang::Lang;
+
+/// Language of an item's text: the ISO 639-1 code where one exists (`zh`, `en`, `ja`),
+/// otherwise the ISO 639-3 code of the detector. `None` when the text is too short or
+/// too mixed to tell.
+pub fn detect(text: &str) -> Option<String> {
+    let info = whatlang::detect(text).filter(|i| i.is_reliable())?;
+    Some(
+        iso_639_1(info.lang())
+            .unwrap_or(info.lang().code())
+            .to_string(),
+    )
+}
+
+fn iso_639_1(lang: Lang) -> Option<&'static str> {
+    Some(match lang {
+        Lang::Eng => "en",
+        Lang::Cmn => "zh",
+        Lang::Jpn => "ja",
+        Lang::Kor => "ko",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Nld => "nl",
+        Lang::Rus => "ru",
+        Lang::Ukr => "uk",
+        Lang::Pol => "pl",
+        Lang::Tur => "tr",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Vie => "vi",
+        Lang::Tha => "th",
+        Lang::Ind => "id",
+        Lang::Swe => "sv",
+        _ => return None,
+    })
+}
@@ -0,0 +1,250 @@
+use crate::adblock::Blocked;
+use crate::config::LinksConfig;
+use crate::html::HtmlContent;
+use anyhow::{Context, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Signal list compiled into the binary, used when `links.list_path` is not set.
+const BUNDLED_LIST: &str = include_str!("../assets/link_signals.toml");
+
+/// Affiliate links shown in the prompt and stored with the review.
+const MAX_LISTED: usize = 5;
+
+#[derive(Debug, Default, Deserialize)]
+struct ListFile {
+    #[serde(default)]
+    affiliate_domains: Vec<String>,
+    #[serde(default)]
+    affiliate_params: Vec<String>,
+    #[serde(default)]
+    tracker_domains: Vec<String>,
+    #[serde(default)]
+    tracker_params: Vec<String>,
+    #[serde(default)]
+    redirector_domains: Vec<String>,
+}
+
+/// Domains and query parameters that mark affiliate and tracking links.
+#[derive(Debug, Clone)]
+pub struct LinkLists {
+    affiliate_domains: Vec<String>,
+    /// `(host, param)`; without a host the parameter counts everywhere
+    affiliate_params: Vec<(Option<String>, String)>,
+    tracker_domains: Vec<String>,
+    tracker_params: Vec<String>,
+    redirector_domains: Vec<String>,
+}
+
+/// What the links of one item give away.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkFeatures {
+    /// Outbound http(s) links
+    pub links: usize,
+    /// Normalized affiliate links, the first few only
+    pub affiliate_links: Vec<String>,
+    pub affiliate: usize,
+    /// Links to tracker hosts or carrying campaign parameters
+    pub tracked: usize,
+    /// 1x1 images and images served by tracker hosts
+    pub pixels: usize,
+    /// `affiliate / links + 0.5 * tracked / links + 0.2` when any tracking pixel is present, capped at 1
+    pub score: f32,
+    /// Links, images and frames matched by the `[adblock]` lists
+    pub blocked: Vec<Blocked>,
+    /// Links followed by `[resolver]` to another URL
+    pub redirected: usize,
+    /// Final domains of redirected links, without duplicates
+    pub destinations: Vec<String>,
+}
+
+impl LinkLists {
+    /// Load `cfg.list_path`, or the bundled list.
+    pub fn load(cfg: &LinksConfig) -> Result<Self> {
+        let (src, name) = match &cfg.list_path {
+            Some(path) => (
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("link_list_read_failed: {}", path))?,
+                path.as_str(),
+            ),
+            None => (BUNDLED_LIST.to_string(), "bundled"),
+        };
+        let file: ListFile = config::Config::builder()
+            .add_source(config::File::from_str(&src, config::FileFormat::Toml))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .with_context(|| format!("link_list_invalid: {}", name))?;
+        let lower = |v: Vec<String>| -> Vec<String> {
+            v.into_iter().map(|s| s.trim().to_lowercase()).collect()
+        };
+        Ok(Self {
+            affiliate_domains: lower(file.affiliate_domains),
+            affiliate_params: lower(file.affiliate_params)
+                .into_iter()
+                .map(|p| match p.split_once(':') {
+                    Some((host, param)) => (Some(host.to_string()), param.to_string()),
+                    None => (None, p),
+                })
+                .collect(),
+            tracker_domains: lower(file.tracker_domains),
+            tracker_params: lower(file.tracker_params),
+            redirector_domains: lower(file.redirector_domains),
+        })
+    }
+
+    /// Score the links and images of an item. Relative URLs are resolved against `base`;
+    /// `redirects` maps links to where their redirects end, and a link counts as affiliate
+    /// or tracked when either end is.
+    pub fn analyze(
+        &self,
+        html: &HtmlContent,
+        base: Option<&str>,
+        redirects: &HashMap<String, Url>,
+    ) -> LinkFeatures {
+        let base = base.and_then(|b| Url::parse(b).ok());
+        let mut f = LinkFeatures::default();
+        for link in &html.links {
+            let Some(url) = resolve(&link.href, base.as_ref()) else {
+                continue;
+            };
+            f.links += 1;
+            let destination = redirects.get(url.as_str());
+            if let Some(dest) = destination {
+                f.redirected += 1;
+                let host = host_of(dest);
+                if !f.destinations.contains(&host) {
+                    f.destinations.push(host);
+                }
+            }
+            let ends = || std::iter::once(&url).chain(destination);
+            if ends().any(|u| self.is_affiliate(u)) {
+                f.affiliate += 1;
+                if f.affiliate_links.len() < MAX_LISTED {
+                    f.affiliate_links
+                        .push(self.normalize(destination.unwrap_or(&url).clone()));
+                }
+            }
+            if ends().any(|u| self.is_tracked(u)) {
+                f.tracked += 1;
+            }
+        }
+        f.pixels = html
+            .image_srcs
+            .iter()
+            .filter(|src| {
+                html.pixels.contains(src)
+                    || resolve(src, base.as_ref())
+                        .is_some_and(|u| matches_domain(&host_of(&u), &self.tracker_domains))
+            })
+            .count();
+        let per_link = |n: usize| {
+            if f.links == 0 {
+                0.0
+            } else {
+                n as f32 / f.links as f32
+            }
+        };
+        f.score =
+            (per_link(f.affiliate) + 0.5 * per_link(f.tracked) + 0.2 * f.pixels.min(1) as f32)
+                .min(1.0);
+        f
+    }
+
+    /// Known URL shorteners and click redirectors.
+    pub fn redirector_domains(&self) -> &[String] {
+        &self.redirector_domains
+    }
+
+    fn is_affiliate(&self, url: &Url) -> bool {
+        let host = host_of(url);
+        matches_domain(&host, &self.affiliate_domains)
+            || self.affiliate_params.iter().any(|(scope, param)| {
+                url.query_pairs().any(|(k, _)| k.to_lowercase() == *param)
+                    && scope.as_deref().is_none_or(|s| matches_domain(&host, &[s]))
+            })
+    }
+
+    fn is_tracked(&self, url: &Url) -> bool {
+        matches_domain(&host_of(url), &self.tracker_domains)
+            || url
+                .query_pairs()
+                .any(|(k, _)| self.tracker_params.contains(&k.to_lowercase()))
+    }
+
+    /// Drop the fragment and tracking parameters, keep everything else.
+    fn normalize(&self, mut url: Url) -> String {
+        url.set_fragment(None);
+        let kept: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(k, _)| !self.tracker_params.contains(&k.to_lowercase()))
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+        url.to_string()
+    }
+}
+
+impl LinkFeatures {
+    pub fn is_empty(&self) -> bool {
+        self.links == 0 && self.pixels == 0 && self.blocked.is_empty()
+    }
+}
+
+impl fmt::Display for LinkFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+        write!(
+            f,
+            "links={} affiliate={} tracked={} tracking_pixels={} score={:.2}",
+            self.links, self.affiliate, self.tracked, self.pixels, self.score
+        )?;
+        if !self.blocked.is_empty() {
+            write!(f, " blocked={}", self.blocked.len())?;
+        }
+        if self.redirected > 0 {
+            write!(f, " redirected={}", self.redirected)?;
+        }
+        for link in &self.affiliate_links {
+            write!(f, "\naffiliate: {}", link)?;
+        }
+        for b in self.blocked.iter().take(MAX_LISTED) {
+            write!(f, "\nblocked: {} ({})", b.url, b.filter)?;
+        }
+        for d in self.destinations.iter().take(MAX_LISTED) {
+            write!(f, "\nredirects to: {}", d)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn resolve(href: &str, base: Option<&Url>) -> Option<Url> {
+    let url = match base {
+        Some(b) => b.join(href).ok()?,
+        None => Url::parse(href).ok()?,
+    };
+    matches!(url.scheme(), "http" | "https").then_some(url)
+}
+
+/// Lowercase host without a leading `www.`.
+pub fn host_of(url: &Url) -> String {
+    let host = url.host_str().unwrap_or("").to_lowercase();
+    host.strip_prefix("www.")
+        .map(str::to_string)
+        .unwrap_or(host)
+}
+
+/// `host` is one of `domains` or a subdomain of one.
+pub fn matches_domain<S: AsRef<str>>(host: &str, domains: &[S]) -> bool {
+    domains.iter().any(|d| {
+        let d = d.as_ref();
+        host == d || host.strip_suffix(d).is_some_and(|rest| rest.ends_with('.'))
+    })
+}
@@ -1,15 +1,36 @@
 use anyhow::Result;
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 use tracing::{error, info};
 
+mod adblock;
+mod breaker;
+mod budget;
+mod calibration;
 mod config;
 mod db;
+mod eval;
+mod fewshot;
 mod freshrss;
+mod fulltext;
 mod greader;
+mod html;
+mod images;
+mod injection;
+mod input;
+mod language;
+mod links;
 mod openai_client;
 mod processor;
+mod profile;
+mod ratelimit;
+mod resolver;
+mod rules;
 mod scheduler;
+mod shadow;
+mod stats;
+mod template;
+mod verdict;
 
 #[derive(Parser, Debug)]
 #[command(name = "freshrss-filter")]
@@ -30,6 +51,92 @@ struct Cli {
     /// Verbose logging
     #[arg(short, long, action = ArgAction::Count)]
     verbose: u8,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Re-run items reviewed under an older prompt, model or classifier settings
+    Reclassify {
+        /// Only re-run items that are still unread in FreshRSS
+        #[arg(long, action = ArgAction::SetTrue)]
+        unread_only: bool,
+
+        /// Only re-run items whose confidence is within this distance of the threshold
+        #[arg(long, value_name = "MARGIN")]
+        grey_zone: Option<f32>,
+
+        /// Maximum number of items to re-run
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Record the correct verdict for a reviewed item
+    Feedback {
+        /// FreshRSS item id
+        item_id: String,
+        verdict: Verdict,
+    },
+    /// Measure a classifier config against labeled examples
+    Eval {
+        /// JSONL dataset, one `{"text": ..., "is_ad": ...}` per line; defaults to feedback stored in the database
+        #[arg(long)]
+        dataset: Option<PathBuf>,
+
+        /// TOML file whose `[openai]` table overrides the main config for this run
+        #[arg(long)]
+        classifier: Option<PathBuf>,
+
+        /// Second classifier config to compare side by side
+        #[arg(long)]
+        compare: Option<PathBuf>,
+
+        /// Concurrent classifier requests
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+
+        /// Where to write misclassified examples (JSONL)
+        #[arg(long, default_value = "eval-misclassified.jsonl")]
+        misclassified: PathBuf,
+    },
+    /// Fit a confidence calibration per model from feedback and store it
+    Calibrate {
+        #[arg(long, value_enum, default_value = "isotonic")]
+        method: calibration::CalibrationMethod,
+    },
+    /// Recommend (or store) the threshold that reaches a target precision on feedback
+    Tune {
+        /// Required precision, e.g. 0.99 for at most 1% false positives
+        #[arg(long, default_value_t = 0.99)]
+        target_precision: f64,
+
+        /// Model to tune; defaults to `openai.model`
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Store the recommended threshold for use with `calibration.use_tuned_threshold`
+        #[arg(long, action = ArgAction::SetTrue)]
+        apply: bool,
+    },
+    /// Show review counts, LLM spend against the budget and recent runs
+    Stats {
+        /// Number of recent runs to list
+        #[arg(long, default_value_t = 10)]
+        runs: usize,
+    },
+    /// Compare shadow classifier verdicts with the primary classifier
+    ShadowReport {
+        /// Maximum number of disagreements to list
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Verdict {
+    Ad,
+    NotAd,
 }
 
 #[tokio::main]
@@ -41,6 +148,7 @@ async fn main() -> Result<()> {
     let cfg = cfg.with_overrides(cli.dry_run);
 
     info!(config = ?cfg, "config_loaded");
+    budget::check_pricing(&cfg)?;
 
     let db = db::Database::new(&cfg.database.path).await?;
 
@@ -53,7 +161,16 @@ async fn main() -> Result<()> {
     } else {
         None
     };
-    let llm = openai_client::OpenAiClient::new(cfg.openai.clone());
+    let llm = openai_client::OpenAiClient::new(cfg.openai.clone())?
+        .with_filters(&cfg.filters)
+        .with_interest(&cfg.interest);
+    let shadow_llm = cfg
+        .shadow
+        .as_ref()
+        .map(|o| openai_client::OpenAiClient::new(o.apply(&cfg.openai)))
+        .transpose()?;
+
+    let adblock = adblock::AdblockFilters::load(&cfg.adblock)?;
 
     let shared_state = processor::ProcessorState::default();
     let proc = processor::Processor::new(
@@ -61,10 +178,80 @@ async fn main() -> Result<()> {
         fr_client,
         gr_client,
         llm,
+        shadow_llm,
+        adblock,
         cfg.clone(),
         shared_state.clone(),
     );
 
+    match cli.command {
+        Some(Command::Reclassify {
+            unread_only,
+            grey_zone,
+            limit,
+        }) => {
+            proc.reclassify(processor::ReclassifyOptions {
+                unread_only,
+                grey_zone,
+                limit,
+            })
+            .await?;
+            return Ok(());
+        }
+        Some(Command::Feedback { item_id, verdict }) => {
+            let is_ad = matches!(verdict, Verdict::Ad);
+            db.save_feedback(&item_id, is_ad, "manual").await?;
+            info!(item_id = %item_id, is_ad, "feedback_saved");
+            return Ok(());
+        }
+        Some(Command::Eval {
+            dataset,
+            classifier,
+            compare,
+            concurrency,
+            misclassified,
+        }) => {
+            eval::run(
+                &cfg,
+                &db,
+                eval::EvalOptions {
+                    dataset,
+                    classifier,
+                    compare,
+                    concurrency,
+                    misclassified,
+                },
+            )
+            .await?;
+            return Ok(());
+        }
+        Some(Command::Calibrate { method }) => {
+            calibration::calibrate(&cfg, &db, method).await?;
+            return Ok(());
+        }
+        Some(Command::Tune {
+            target_precision,
+            model,
+            apply,
+        }) => {
+            let model = model.unwrap_or_else(|| cfg.openai.model.clone());
+            calibration::tune(&cfg, &db, &model, target_precision, apply).await?;
+            return Ok(());
+        }
+        Some(Command::Stats { runs }) => {
+            stats::show(&cfg, &db, runs).await?;
+            return Ok(());
+        }
+        Some(Command::ShadowReport { limit }) => {
+            let Some(overrides) = &cfg.shadow else {
+                anyhow::bail!("shadow_not_configured: add a [shadow] table to the config");
+            };
+            shadow::report(&db, &cfg.openai, &overrides.apply(&cfg.openai), limit).await?;
+            return Ok(());
+        }
+        None => {}
+    }
+
     if cli.once {
         proc.run_once().await?;
         return Ok(());
@@ -90,7 +277,7 @@ async fn main() -> Result<()> {
             if sec == "0" {
                 // advance to next minute boundary
                 let add_secs = (60 - t.second()) % 60;
-                t = t + ChronoDuration::seconds(add_secs as i64);
+                t += ChronoDuration::seconds(add_secs as i64);
                 if let Some(tt) = t.with_second(0) {
                     t = tt;
                 }
@@ -98,13 +285,13 @@ async fn main() -> Result<()> {
                 if let Some(step_str) = min.strip_prefix("*/") {
                     if let Ok(step) = step_str.parse::<u32>() {
                         // advance to minute divisible by step
-                        while t.minute() % step != 0 {
-                            t = t + ChronoDuration::minutes(1);
+                        while !t.minute().is_multiple_of(step) {
+                            t += ChronoDuration::minutes(1);
                         }
                     }
                 } else if let Ok(target_min) = min.parse::<u32>() {
                     while t.minute() != target_min {
-                        t = t + ChronoDuration::minutes(1);
+                        t += ChronoDuration::minutes(1);
                     }
                 } else {
                     // unknown minute field, default next minute
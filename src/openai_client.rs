@@ -1,11 +1,33 @@
-use crate::config::OpenAiConfig;
+use crate::breaker::FailureClass;
+use crate::config::{FilterProfile, InterestConfig, ModelPrice, OpenAiConfig, RetryConfig};
+use crate::fewshot::FewShotExample;
+use crate::profile::{Profile, VerdictShape};
+use crate::ratelimit::{RateLimiter, estimate_tokens};
+use crate::template::{PromptTemplates, PromptVars};
+use crate::verdict::{self, REPAIR_PROMPT, VerdictParse};
 use anyhow::{Result, anyhow};
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, StatusCode, header::HeaderMap};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tracing::{instrument, warn};
+use tracing::{debug, instrument, warn};
 
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Introduces the `[filters]` profiles in the system prompt.
+const FILTER_PROMPT: &str = "Also judge the item against each of the topic filters below, independently of whether it is an ad. \
+Add a \"filters\" object to your JSON with one entry per filter name: {\"match\": boolean, \"confidence\": number between 0 and 1}.
+Filters:";
+
+/// Introduces the `[interest]` reader profile in the system prompt.
+const INTEREST_PROMPT: &str = "Also rate how valuable the item is to the reader described below, independently of whether it is an ad. \
+Add an \"interest\" number between 0 (irrelevant) and 1 (must read) to your JSON.
+Reader:";
+
+/// Rate-limit estimate for one image input, a high-detail tile budget.
+const IMAGE_TOKENS: u64 = 765;
 
 #[derive(Debug, Clone)]
 pub struct OpenAiApiError {
@@ -17,6 +39,23 @@ impl OpenAiApiError {
     pub fn new(status: StatusCode, body: Value) -> Self {
         Self { status, body }
     }
+
+    /// Provider error code, e.g. `insufficient_quota` or `rate_limit_exceeded`.
+    pub fn code(&self) -> Option<&str> {
+        self.body
+            .get("code")
+            .or_else(|| self.body.get("error").and_then(|e| e.get("code")))
+            .and_then(|c| c.as_str())
+    }
+
+    /// Whether retrying the same request later may succeed. Exhausted quota is not
+    /// retryable even though providers report it as 429.
+    pub fn is_retryable(&self) -> bool {
+        let status_retryable = self.status == StatusCode::REQUEST_TIMEOUT
+            || self.status == StatusCode::TOO_MANY_REQUESTS
+            || self.status.is_server_error();
+        status_retryable && self.code() != Some("insufficient_quota")
+    }
 }
 
 impl fmt::Display for OpenAiApiError {
@@ -31,6 +70,96 @@ impl std::error::Error for OpenAiApiError {}
 pub struct OpenAiClient {
     client: Client,
     cfg: OpenAiConfig,
+    templates: PromptTemplates,
+    /// `(name, description)` of the `[filters]` profiles, sorted by name
+    filters: Vec<(String, String)>,
+    /// The `[interest]` reader profile, when interest is scored
+    interest: Option<String>,
+    /// `[openai]` first, then its fallbacks in order. Shared by clones so health
+    /// and rate limits are tracked across concurrent workers and runs.
+    providers: Arc<Vec<Provider>>,
+}
+
+/// One endpoint in the fallback chain.
+struct Provider {
+    name: String,
+    api_key: Option<String>,
+    model: String,
+    api_base: String,
+    profile: Profile,
+    limiter: RateLimiter,
+    cooldown_until: Mutex<Option<Instant>>,
+}
+
+impl Provider {
+    fn cooling_down(&self, now: Instant) -> bool {
+        self.cooldown_until
+            .lock()
+            .expect("provider lock")
+            .is_some_and(|t| t > now)
+    }
+
+    fn set_cooldown(&self, until: Option<Instant>) {
+        *self.cooldown_until.lock().expect("provider lock") = until;
+    }
+}
+
+/// Display name for an endpoint: the configured one, or the host of `api_base`.
+fn provider_name(name: Option<&str>, api_base: &str) -> String {
+    name.map(str::to_string).unwrap_or_else(|| {
+        reqwest::Url::parse(api_base)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| api_base.to_string())
+    })
+}
+
+/// A failed attempt and whether it is worth retrying.
+struct AttemptError {
+    error: anyhow::Error,
+    retryable: bool,
+    retry_after: Option<Duration>,
+}
+
+impl AttemptError {
+    fn transport(e: reqwest::Error) -> Self {
+        let retryable = e.is_timeout() || e.is_connect() || e.is_request() || e.is_body();
+        Self {
+            error: e.into(),
+            retryable,
+            retry_after: None,
+        }
+    }
+}
+
+/// Identifies the prompt, model and settings that produced a verdict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassifierVersion {
+    pub prompt_hash: String,
+    pub model: String,
+    pub api_base: String,
+    pub settings: String,
+}
+
+impl ClassifierVersion {
+    pub fn of(cfg: &OpenAiConfig) -> Self {
+        Self {
+            prompt_hash: cfg.prompt_fingerprint(),
+            model: cfg.model.clone(),
+            api_base: cfg.api_base.clone(),
+            settings: cfg.settings_fingerprint(),
+        }
+    }
+
+    /// Fold a setting kept outside `[openai]` into the settings fingerprint.
+    pub fn with_setting(mut self, key: &str, value: Value) -> Self {
+        let mut settings: Value = serde_json::from_str(&self.settings).unwrap_or_default();
+        if let Some(map) = settings.as_object_mut() {
+            map.insert(key.to_string(), value);
+            self.settings = settings.to_string();
+        }
+        self
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -38,142 +167,530 @@ pub struct ClassifierResponse {
     pub is_ad: bool,
     pub confidence: f32,
     pub reason: String,
+    /// Verdicts on the `[filters]` profiles, by name
+    #[serde(default)]
+    pub filters: HashMap<String, FilterVerdict>,
+    /// Value to the `[interest]` reader profile, 0 to 1
+    #[serde(default)]
+    pub interest: Option<f32>,
 }
 
-impl OpenAiClient {
-    pub fn new(cfg: OpenAiConfig) -> Self {
-        let client = Client::builder().build().unwrap();
-        Self { client, cfg }
-    }
-
-    #[instrument(name = "Reviewing content", skip(self, text))]
-    pub async fn classify(&self, text: &str) -> Result<ClassifierResponse> {
-        #[derive(Serialize)]
-        struct ReqBody<'a> {
-            model: &'a str,
-            response_format: RespFmt,
-            messages: Vec<Message<'a>>,
-            #[serde(skip_serializing_if = "Option::is_none")]
-            temperature: Option<f32>,
-            #[serde(skip_serializing_if = "Option::is_none")]
-            max_tokens: Option<u32>,
-        }
-        #[derive(Serialize)]
-        struct RespFmt {
-            r#type: &'static str,
-        }
-        #[derive(Serialize)]
-        struct Message<'a> {
-            role: &'static str,
-            content: &'a str,
-        }
-
-        let body = ReqBody {
-            model: &self.cfg.model,
-            response_format: RespFmt {
-                r#type: "json_object",
-            },
-            messages: vec![
-                Message {
-                    role: "system",
-                    content: &self.cfg.system_prompt,
-                },
-                Message {
-                    role: "user",
-                    content: text,
-                },
-            ],
-            temperature: self.cfg.temperature,
-            max_tokens: self.cfg.max_tokens,
-        };
+/// Whether an item matches one filter profile.
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub struct FilterVerdict {
+    #[serde(rename = "match")]
+    pub matched: bool,
+    #[serde(default)]
+    pub confidence: f32,
+}
 
-        let url = format!(
-            "{}/chat/completions",
-            self.cfg.api_base.trim_end_matches('/')
-        );
-        let resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.cfg.api_key)
-            .json(&body)
-            .send()
-            .await?;
+/// Token counts reported in the `usage` block of a response.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Part of `prompt_tokens` served from the provider's prompt cache
+    pub cached_tokens: u64,
+}
 
-        let status = resp.status();
-        let v: Value = resp.json().await?;
+impl TokenUsage {
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cached_tokens += other.cached_tokens;
+    }
 
-        if let Some(err) = v.get("error") {
-            return Err(OpenAiApiError::new(status, err.clone()).into());
+    /// Cost in USD under the given price.
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        let cached = self.cached_tokens.min(self.prompt_tokens);
+        let uncached = self.prompt_tokens - cached;
+        (uncached as f64 * price.input
+            + cached as f64 * price.cached_input.unwrap_or(price.input)
+            + self.completion_tokens as f64 * price.output)
+            / 1_000_000.0
+    }
+}
+
+/// A parsed verdict together with the usage of the request that produced it.
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub verdict: ClassifierResponse,
+    pub usage: TokenUsage,
+    /// Endpoint that answered, its base URL and the model it ran
+    pub provider: String,
+    pub api_base: String,
+    pub model: String,
+    pub parse: VerdictParse,
+}
+
+impl Classification {
+    /// `base` with the model and endpoint that answered, which differ from the
+    /// configured ones after a failover.
+    pub fn version(&self, base: &ClassifierVersion) -> ClassifierVersion {
+        ClassifierVersion {
+            model: self.model.clone(),
+            api_base: self.api_base.clone(),
+            ..base.clone()
         }
+    }
+}
 
-        if !status.is_success() {
-            return Err(OpenAiApiError::new(status, v).into());
+impl OpenAiClient {
+    pub fn new(cfg: OpenAiConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(cfg.retry.request_timeout_secs))
+            .build()?;
+        let templates = PromptTemplates::compile(&cfg)?;
+        let mut providers = vec![Provider {
+            name: provider_name(cfg.name.as_deref(), &cfg.api_base),
+            api_key: Some(cfg.api_key.clone()),
+            model: cfg.model.clone(),
+            api_base: cfg.api_base.clone(),
+            profile: Profile::resolve(cfg.profile.as_ref(), &cfg.model, &cfg.api_base),
+            limiter: RateLimiter::new(&cfg.rate_limit),
+            cooldown_until: Mutex::new(None),
+        }];
+        for f in &cfg.fallbacks {
+            let model = f.model.clone().unwrap_or_else(|| cfg.model.clone());
+            providers.push(Provider {
+                name: provider_name(f.name.as_deref(), &f.api_base),
+                api_key: f.api_key.clone(),
+                profile: Profile::resolve(f.profile.as_ref(), &model, &f.api_base),
+                model,
+                api_base: f.api_base.clone(),
+                limiter: RateLimiter::new(&cfg.rate_limit),
+                cooldown_until: Mutex::new(None),
+            });
         }
+        for p in &providers {
+            debug!(provider = %p.name, preset = ?p.profile.preset, url = %p.profile.url, "openai_provider_configured");
+        }
+        Ok(Self {
+            client,
+            cfg,
+            templates,
+            filters: Vec::new(),
+            interest: None,
+            providers: Arc::new(providers),
+        })
+    }
 
-        // Extract content
-        let raw = v["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("{}");
-        let content = strip_code_fences(raw);
-        parse_classifier_response(&content, raw)
+    /// Also judge every item against these topic filters, in the same request.
+    pub fn with_filters(mut self, filters: &HashMap<String, FilterProfile>) -> Self {
+        self.filters = filters
+            .iter()
+            .map(|(name, f)| (name.clone(), f.description.trim().to_string()))
+            .collect();
+        self.filters.sort();
+        self
+    }
+
+    /// Also score every item against the reader profile, in the same request.
+    pub fn with_interest(mut self, interest: &InterestConfig) -> Self {
+        let profile = interest.profile.trim();
+        self.interest = (!profile.is_empty()).then(|| profile.to_string());
+        self
     }
-}
 
-fn parse_classifier_response(content: &str, raw: &str) -> Result<ClassifierResponse> {
-    match serde_json::from_str::<ClassifierResponse>(content) {
-        Ok(parsed) => Ok(parsed),
-        Err(primary_err) => {
-            let responses: Vec<ClassifierResponse> =
-                serde_json::from_str(content).map_err(|secondary_err| {
-                    anyhow!(
-                        "parse_classifier_response_failed: {} (array_parse_error: {}) raw={}",
-                        primary_err,
-                        secondary_err,
-                        raw
-                    )
-                })?;
+    /// Whether few-shot examples fit the answer asked for: their confirmed verdicts
+    /// only cover `is_ad`, not filters or interest.
+    pub fn takes_examples(&self) -> bool {
+        self.extra_prompt().is_none()
+    }
 
-            if responses.is_empty() {
-                return Err(anyhow!(
-                    "parse_classifier_response_failed: empty array raw={}",
-                    raw
-                ));
+    /// Whether the primary endpoint is sent image inputs.
+    pub fn vision(&self) -> bool {
+        self.providers.first().is_some_and(|p| p.profile.vision)
+    }
+
+    pub fn version(&self) -> ClassifierVersion {
+        let mut version = ClassifierVersion::of(&self.cfg);
+        if let Some(prompt) = self.extra_prompt() {
+            version.prompt_hash = format!(
+                "{:x}",
+                md5::compute(format!("{}\n{}", version.prompt_hash, prompt))
+            );
+        }
+        version
+    }
+
+    /// Instructions appended to the system prompt when filters or interest scoring
+    /// are configured.
+    fn extra_prompt(&self) -> Option<String> {
+        let mut sections = Vec::new();
+        if !self.filters.is_empty() {
+            let mut prompt = String::from(FILTER_PROMPT);
+            for (name, description) in &self.filters {
+                prompt.push_str(&format!("\n- {}: {}", name, description));
             }
+            sections.push(prompt);
+        }
+        if let Some(profile) = &self.interest {
+            sections.push(format!("{}\n{}", INTEREST_PROMPT, profile));
+        }
+        (!sections.is_empty()).then(|| sections.join("\n\n"))
+    }
 
-            warn!(
-                error = %primary_err,
-                count = responses.len(),
-                "classifier_response_array_detected"
+    #[instrument(name = "Reviewing content", skip(self, vars))]
+    pub async fn classify(
+        &self,
+        vars: &PromptVars,
+        examples: &[FewShotExample],
+    ) -> Result<Classification> {
+        let mut system = self
+            .templates
+            .system_for(vars.language.as_deref())
+            .render(vars);
+        if let Some(prompt) = self.extra_prompt() {
+            system = format!("{}\n\n{}", system, prompt);
+        }
+        let filter_names: Vec<&str> = self.filters.iter().map(|(n, _)| n.as_str()).collect();
+        let shape = VerdictShape {
+            filters: &filter_names,
+            interest: self.interest.is_some(),
+        };
+        let text = &self.templates.user.render(vars);
+        // Each example is a user turn rendered like the item, answered with its confirmed verdict.
+        let shots: Vec<(String, String)> = examples
+            .iter()
+            .map(|e| {
+                let answer = serde_json::json!({
+                    "is_ad": e.is_ad,
+                    "confidence": 1.0,
+                    "reason": "confirmed by reviewer",
+                });
+                (self.templates.user.render(&e.vars), answer.to_string())
+            })
+            .collect();
+        let mut turns: Vec<(&str, &str)> = Vec::with_capacity(shots.len() * 2 + 1);
+        for (question, answer) in &shots {
+            turns.push(("user", question));
+            turns.push(("assistant", answer));
+        }
+        turns.push(("user", text.as_str()));
+        let estimated = estimate_tokens(&system)
+            + turns.iter().map(|(_, t)| estimate_tokens(t)).sum::<u64>()
+            + vars.image_inputs.len() as u64 * IMAGE_TOKENS
+            + self.cfg.max_tokens.unwrap_or(256) as u64;
+
+        let now = Instant::now();
+        let mut chain: Vec<&Provider> = self
+            .providers
+            .iter()
+            .filter(|p| !p.cooling_down(now))
+            .collect();
+        if chain.is_empty() {
+            // Everything is cooling down; trying in order beats failing outright.
+            chain = self.providers.iter().collect();
+        }
+
+        let mut last_err = None;
+        let mut answered = None;
+        for provider in chain {
+            let body = provider.profile.build_body(
+                &provider.model,
+                &system,
+                &turns,
+                &vars.image_inputs,
+                &shape,
+                self.cfg.temperature,
+                self.cfg.max_tokens,
             );
+            let url = &provider.profile.url;
+            match self.post_with_retry(provider, url, &body, estimated).await {
+                Ok(v) => {
+                    provider.set_cooldown(None);
+                    answered = Some((provider, v));
+                    break;
+                }
+                Err(e) if self.providers.len() > 1 && should_fail_over(&e) => {
+                    warn!(provider = %provider.name, error = %e, cooldown_secs = self.cfg.provider_cooldown_secs, "openai_provider_failed_over");
+                    provider.set_cooldown(Some(
+                        Instant::now() + Duration::from_secs(self.cfg.provider_cooldown_secs),
+                    ));
+                    last_err = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let Some((provider, v)) = answered else {
+            return Err(last_err.unwrap_or_else(|| anyhow!("openai_no_provider")));
+        };
+
+        let mut usage = provider.profile.extract_usage(&v);
+        let raw = provider.profile.extract_content(&v);
+        let (verdict, parse) = match verdict::parse(&raw) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                // One repair round-trip on the same provider before giving up.
+                warn!(provider = %provider.name, error = %err, "classifier_response_repairing");
+                let body = provider.profile.build_body(
+                    &provider.model,
+                    &system,
+                    &[&turns[..], &[("assistant", &raw), ("user", REPAIR_PROMPT)]].concat(),
+                    &[],
+                    &shape,
+                    self.cfg.temperature,
+                    self.cfg.max_tokens,
+                );
+                // The first call was answered and must still be charged if this one fails.
+                let v = self
+                    .post_with_retry(provider, &provider.profile.url, &body, estimated * 2)
+                    .await
+                    .map_err(|e| verdict::VerdictParseError {
+                        detail: format!("{} (repair request failed: {})", err.detail, e),
+                        raw: raw.clone(),
+                        usage,
+                        model: provider.model.clone(),
+                    })?;
+                usage.add(&provider.profile.extract_usage(&v));
+                let repaired = provider.profile.extract_content(&v);
+                let (verdict, _) =
+                    verdict::parse(&repaired).map_err(|e| verdict::VerdictParseError {
+                        detail: format!("{} (after repair)", e.detail),
+                        raw: raw.clone(),
+                        usage,
+                        model: provider.model.clone(),
+                    })?;
+                (verdict, VerdictParse::Repaired)
+            }
+        };
+        Ok(Classification {
+            verdict,
+            usage,
+            provider: provider.name.clone(),
+            api_base: provider.api_base.clone(),
+            model: provider.model.clone(),
+            parse,
+        })
+    }
+}
 
-            if let Some(best_ad) = responses
-                .iter()
-                .filter(|r| r.is_ad)
-                .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
-            {
-                return Ok(best_ad.clone());
+impl OpenAiClient {
+    /// POST `body` under the shared rate limiter, retrying rate limits, server errors,
+    /// timeouts and dropped connections with exponential backoff and jitter.
+    async fn post_with_retry<B: Serialize + ?Sized>(
+        &self,
+        provider: &Provider,
+        url: &str,
+        body: &B,
+        estimated_tokens: u64,
+    ) -> Result<Value> {
+        let retry = &self.cfg.retry;
+        let started = Instant::now();
+        let max_total = Duration::from_secs(retry.max_total_secs);
+        let mut attempt = 0u32;
+        loop {
+            attempt += 1;
+            provider.limiter.acquire(estimated_tokens).await;
+            let failure = match self.attempt(provider, url, body).await {
+                Ok(v) => return Ok(v),
+                Err(f) => f,
+            };
+            if !failure.retryable || attempt >= retry.max_attempts.max(1) {
+                return Err(failure.error);
+            }
+            // A provider may ask for any wait; past the total budget it makes no difference.
+            let delay = failure
+                .retry_after
+                .map(|d| d.min(max_total) + Duration::from_millis(rand::random_range(0..250)))
+                .unwrap_or_else(|| backoff(retry, attempt));
+            if started.elapsed() + delay > max_total {
+                warn!(attempt, error = %failure.error, "openai_retry_budget_exhausted");
+                return Err(failure.error);
             }
+            warn!(
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                error = %failure.error,
+                "openai_retrying"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn attempt<B: Serialize + ?Sized>(
+        &self,
+        provider: &Provider,
+        url: &str,
+        body: &B,
+    ) -> std::result::Result<Value, AttemptError> {
+        let req = provider.profile.authorize(
+            self.client.post(url).json(body),
+            provider.api_key.as_deref(),
+        );
+        let resp = req.send().await.map_err(AttemptError::transport)?;
+
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        if let Some(wait) = rate_limit_reset(&headers) {
+            let wait = wait.min(Duration::from_secs(self.cfg.retry.max_total_secs));
+            provider.limiter.pause_until(Instant::now() + wait).await;
+        }
+        let raw = resp.text().await.map_err(AttemptError::transport)?;
+        let v: Value = serde_json::from_str(&raw).unwrap_or(Value::String(raw));
+
+        let api_err = if let Some(err) = v.get("error") {
+            OpenAiApiError::new(status, err.clone())
+        } else if !status.is_success() {
+            OpenAiApiError::new(status, v)
+        } else {
+            return Ok(v);
+        };
+        Err(AttemptError {
+            retryable: api_err.is_retryable(),
+            retry_after: retry_after(&headers),
+            error: api_err.into(),
+        })
+    }
+}
+
+/// Whether a failure should move the request on to the next provider in the chain.
+fn should_fail_over(err: &anyhow::Error) -> bool {
+    matches!(
+        FailureClass::of(err),
+        Some(FailureClass::Quota | FailureClass::Network)
+    )
+}
 
-            responses
-                .into_iter()
-                .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
-                .ok_or_else(|| anyhow!("parse_classifier_response_failed: empty array raw={}", raw))
+/// Exponential backoff with full jitter between half and all of the nominal delay.
+fn backoff(retry: &RetryConfig, attempt: u32) -> Duration {
+    let nominal = retry
+        .base_delay_ms
+        .saturating_mul(1u64 << (attempt - 1).min(16))
+        .min(retry.max_delay_ms);
+    Duration::from_millis(rand::random_range(nominal / 2..=nominal))
+}
+
+/// Delay requested via `retry-after-ms`, `retry-after` (seconds or HTTP date)
+/// or the `x-ratelimit-reset-*` headers.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return seconds(ms / 1000.0);
+    }
+    if let Some(v) = header("retry-after") {
+        if let Ok(secs) = v.trim().parse::<f64>() {
+            return seconds(secs);
+        }
+        if let Ok(at) = chrono::DateTime::parse_from_rfc2822(v.trim()) {
+            return (at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                .to_std()
+                .ok();
         }
     }
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .iter()
+        .filter_map(|name| header(name).and_then(parse_reset_duration))
+        .max()
+}
+
+/// Time until the provider's rate-limit window reopens, when a
+/// `x-ratelimit-remaining-*` header says it is used up.
+fn rate_limit_reset(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    [
+        (
+            "x-ratelimit-remaining-requests",
+            "x-ratelimit-reset-requests",
+        ),
+        ("x-ratelimit-remaining-tokens", "x-ratelimit-reset-tokens"),
+    ]
+    .iter()
+    .filter(|(remaining, _)| header(remaining).map(str::trim) == Some("0"))
+    .filter_map(|(_, reset)| header(reset).and_then(parse_reset_duration))
+    .max()
+}
+
+/// Parse durations like `1s`, `6m0s`, `20ms` or `1h2m3.5s`.
+fn parse_reset_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<f64>() {
+        return seconds(secs);
+    }
+    let mut total = 0.0f64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let num_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let value: f64 = rest[..num_len].parse().ok()?;
+        rest = &rest[num_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        total += value
+            * match &rest[..unit_len] {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            };
+        rest = &rest[unit_len..];
+    }
+    seconds(total)
+}
+
+/// A duration from a provider-supplied number of seconds. Negative values mean now;
+/// NaN, infinite and out-of-range values are ignored rather than trusted.
+fn seconds(secs: f64) -> Option<Duration> {
+    if !secs.is_finite() {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs.max(0.0)).ok()
 }
 
-fn strip_code_fences(s: &str) -> String {
-    let t = s.trim();
-    if t.starts_with("```") {
-        // remove first line fence and trailing fence
-        let mut lines = t.lines();
-        let _first = lines.next();
-        let rest: String = lines.collect::<Vec<_>>().join("\n");
-        let trimmed = rest.trim_end();
-        if trimmed.ends_with("```") {
-            return trimmed.trim_end_matches("```").trim().to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
         }
-        return trimmed.to_string();
+        map
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_milliseconds() {
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "2")])),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            retry_after(&headers(&[("retry-after-ms", "1500")])),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "-3")])),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn retry_after_ignores_unusable_numbers() {
+        for value in ["inf", "NaN", "-inf"] {
+            assert_eq!(retry_after(&headers(&[("retry-after-ms", value)])), None);
+        }
+        assert_eq!(retry_after(&headers(&[("retry-after", "1e20")])), None);
+    }
+
+    #[test]
+    fn reset_durations() {
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(
+            parse_reset_duration("20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(parse_reset_duration("1e30"), None);
+        assert_eq!(parse_reset_duration("99999999999999999999h"), None);
+        assert_eq!(parse_reset_duration("5x"), None);
     }
-    t.to_string()
 }
@@ -1,19 +1,44 @@
 use crate::{
-    config::Config,
-    db::Database,
-    freshrss::{FreshRssClient, item_text},
+    adblock::{AdblockFilters, Engine},
+    breaker::{CircuitBreaker, CircuitOpenError, OPEN_NOTE},
+    budget::{Budget, BudgetCap},
+    calibration::DecisionPolicy,
+    config::{Config, FilterAction, InjectionAction, InterestAction},
+    db::{Database, ReviewRecord, RunRecord, StoredReview},
+    fewshot::{self, FewShotExample, FewShotPool},
+    freshrss::{FeverFeed, FeverItem, FreshRssClient, item_body, item_html, item_text},
+    fulltext::FullText,
     greader::GReaderClient,
-    openai_client::{OpenAiApiError, OpenAiClient},
+    images::{ImageFetcher, ImageInput},
+    injection::{self, Detector},
+    input::{self, OversizedError, Prepared},
+    language,
+    links::LinkLists,
+    openai_client::{Classification, ClassifierVersion, OpenAiApiError, OpenAiClient, TokenUsage},
+    ratelimit::estimate_tokens,
+    resolver::Resolver,
+    rules::{self, RULES_PROVIDER, RuleInput},
+    template::PromptVars,
+    verdict::{VerdictParse, VerdictParseError},
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use colored::Colorize;
+use futures::future;
 use futures::stream::{self, StreamExt};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::StatusCode;
+use reqwest::{StatusCode, Url};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::future::Future;
 use std::sync::{Arc, Mutex};
 use tracing::instrument;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
+
+/// Tokens of system prompt and item framing added to the item text, for cost estimates.
+const PROMPT_OVERHEAD_TOKENS: u64 = 500;
+
+/// `feedback.source` of decisions read from the grey-zone label.
+const REVIEW_LABEL_SOURCE: &str = "review_label";
 
 #[derive(Clone, Default)]
 pub struct ProcessorState {
@@ -25,17 +50,22 @@ pub struct Processor {
     db: Database,
     fr: FreshRssClient,
     llm: OpenAiClient,
+    shadow: Option<OpenAiClient>,
     gr: Option<GReaderClient>,
+    adblock: AdblockFilters,
     cfg: Config,
     state: ProcessorState,
 }
 
 impl Processor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db: Database,
         fr: FreshRssClient,
         gr: Option<GReaderClient>,
         llm: OpenAiClient,
+        shadow: Option<OpenAiClient>,
+        adblock: AdblockFilters,
         cfg: Config,
         state: ProcessorState,
     ) -> Self {
@@ -44,6 +74,8 @@ impl Processor {
             fr,
             gr,
             llm,
+            shadow,
+            adblock,
             cfg,
             state,
         }
@@ -59,6 +91,14 @@ impl Processor {
         )?);
         fetch_pb.enable_steady_tick(std::time::Duration::from_millis(120));
 
+        let started_at = chrono::Utc::now().to_rfc3339();
+        let ctx = RunContext::load(self).await?;
+        let ctx = &ctx;
+
+        if let Err(e) = self.sync_review_labels().await {
+            warn!(error = %e, "review_label_sync_failed");
+        }
+
         // Fetch items
         let items = self.fr.fetch_unread_items().await?;
         let total = items.len();
@@ -104,7 +144,7 @@ impl Processor {
                 let this = self.clone();
                 async move {
                     let title = item.title.clone();
-                    let res = this.handle_item(item).await;
+                    let res = this.handle_item(item, ctx).await;
                     match &res {
                         Ok(action) => {
                             main_pb.inc(1);
@@ -145,19 +185,16 @@ impl Processor {
 
         // Aggregate results
         let mut counts = ActionCounts::default();
-        for r in &processed {
-            if let Ok(a) = r {
-                match a {
-                    ProcessAction::SkippedExists => counts.skipped_exists += 1,
-                    ProcessAction::Kept => counts.kept += 1,
-                    ProcessAction::MarkedRead => counts.marked_read += 1,
-                    ProcessAction::Labeled => counts.labeled += 1,
-                    ProcessAction::Deleted => counts.deleted += 1,
-                    ProcessAction::WouldAct => counts.would_act += 1,
-                }
-            }
+        for a in processed.iter().flatten() {
+            counts.record(a);
         }
+        let errors = processed.iter().filter(|r| r.is_err()).count() as u64;
         let reviewed = (counts.skipped_exists
+            + counts.oversized
+            + counts.injection_labeled
+            + counts.review_labeled
+            + counts.filtered
+            + counts.promoted
             + counts.kept
             + counts.marked_read
             + counts.labeled
@@ -166,8 +203,30 @@ impl Processor {
         if let Ok(mut s) = self.state.last_run_status.lock() {
             *s = format!("reviewed_items={}/{}", reviewed, total);
         }
+        let stats = ctx.stats();
+        let trip = ctx.breaker.trip();
+        let notes: Vec<String> = trip
+            .iter()
+            .map(|t| t.to_string())
+            .chain(
+                stats
+                    .budget_hit
+                    .map(|cap| format!("budget_exhausted: {}", cap)),
+            )
+            .collect();
+        let note = (!notes.is_empty()).then(|| notes.join("; "));
+        if let Some(t) = &trip {
+            main_pb.suspend(|| {
+                warn!(
+                    "{} 分类服务不可用({})，已中止本轮剩余 {} 条，下次运行先试探",
+                    "[!]".red(),
+                    t.class,
+                    counts.aborted
+                );
+            });
+        }
         main_pb.finish_with_message(format!(
-            "完成 {}/{} | 保留={} 已读={} 已打标={} 已删除={} 已存在={} 预演={}",
+            "完成 {}/{} | 保留={} 已读={} 已打标={} 已删除={} 已存在={} 预演={} 推迟={} 中止={} 超长={} 疑似注入={}(打标={}) 待确认={} 主题过滤={} 推荐={} | 宽松解析={} 修复={} 解析失败={} | tokens={}/{} 费用=${:.4}{}",
             reviewed,
             total,
             counts.kept,
@@ -176,64 +235,1260 @@ impl Processor {
             counts.deleted,
             counts.skipped_exists,
             counts.would_act,
+            counts.deferred,
+            counts.aborted,
+            counts.oversized,
+            stats.injections,
+            counts.injection_labeled,
+            counts.review_labeled,
+            counts.filtered,
+            counts.promoted,
+            stats.lenient_parses,
+            stats.repairs,
+            stats.parse_failures,
+            stats.usage.prompt_tokens,
+            stats.usage.completion_tokens,
+            stats.cost,
+            ctx.budget_summary(stats.cost),
         ));
         status_pb.finish_and_clear();
+
+        self.db
+            .save_run(&RunRecord {
+                started_at,
+                total: total as u64,
+                kept: counts.kept,
+                acted: counts.marked_read + counts.labeled + counts.deleted,
+                skipped: counts.skipped_exists,
+                deferred: counts.deferred + counts.aborted,
+                errors,
+                usage: stats.usage,
+                cost: stats.cost,
+                lenient_parses: stats.lenient_parses,
+                repairs: stats.repairs,
+                parse_failures: stats.parse_failures,
+                oversized: counts.oversized,
+                promoted: counts.promoted,
+                note,
+            })
+            .await?;
         Ok(())
     }
 
-    #[instrument(name = "Reviewing content", skip(self, item), fields(item_id = item.id, title = %item.title))]
-    async fn handle_item(&self, item: crate::freshrss::FeverItem) -> Result<ProcessAction> {
+    #[instrument(name = "Reviewing content", skip(self, item, ctx), fields(item_id = item.id, title = %item.title))]
+    async fn handle_item(&self, item: FeverItem, ctx: &RunContext) -> Result<ProcessAction> {
         let item_id = item.id.to_string();
         if self.db.has_reviewed(&item_id).await? {
             return Ok(ProcessAction::SkippedExists);
         }
-        let text = item_text(&item);
+        let Some(_reservation) = ctx.reserve(self.estimated_cost(&item)) else {
+            return Ok(ProcessAction::Deferred);
+        };
+        if ctx.breaker.trip().is_some() {
+            return Ok(ProcessAction::Aborted);
+        }
+        let item = ctx.with_full_text(item).await;
+        ctx.resolve_links(&item).await;
+        ctx.load_images(&item).await;
+        let (reviewed, ()) = tokio::join!(self.review(&item, ctx), self.shadow_review(&item, ctx));
+        ctx.forget_images(&item);
+        let reviewed = match reviewed {
+            Err(e) if e.is::<CircuitOpenError>() => return Ok(ProcessAction::Aborted),
+            Err(e) if e.is::<OversizedError>() => {
+                self.db
+                    .set_review_action(&item_id, ProcessAction::Oversized.as_str())
+                    .await?;
+                return Ok(ProcessAction::Oversized);
+            }
+            res => res?,
+        };
+        let action = if reviewed.flagged {
+            self.apply_action(&item).await?
+        } else if reviewed.uncertain {
+            self.label_uncertain(&item).await?
+        } else if !reviewed.filters.is_empty() {
+            self.apply_filters(&item, &reviewed.filters).await?
+        } else if reviewed.injection_suspected
+            && self.cfg.injection.action == InjectionAction::Label
+        {
+            self.label_suspected(&item).await?
+        } else if reviewed.promoted {
+            self.promote(&item).await?
+        } else {
+            ProcessAction::Kept
+        };
+        self.db.set_review_action(&item_id, action.as_str()).await?;
+        Ok(action)
+    }
+
+    /// Classify an item and persist the verdict.
+    async fn review(&self, item: &FeverItem, ctx: &RunContext) -> Result<Reviewed> {
+        let policy = &ctx.policy;
+        let item_id = item.id.to_string();
+        let text = item_text(item);
         let hash = format!("{:x}", md5::compute(&text));
-        let res = match self.llm.classify(&text).await {
-            Ok(res) => res,
+        let version = self.version_of(&self.llm);
+        let vars = ctx.prompt_vars(item);
+        let link_features = (!vars.link_features.is_empty())
+            .then(|| serde_json::to_string(&vars.link_features))
+            .transpose()?;
+        let injection = ctx.injection.detect(&vars);
+        let suspected = !injection.is_empty();
+        if suspected {
+            ctx.record_injection();
+            warn!(item_id = %item.id, patterns = %injection.join(", "), "injection_suspected");
+        }
+        let reviewed = |flagged: bool| Reviewed {
+            flagged: flagged || (suspected && self.cfg.injection.action == InjectionAction::Ad),
+            injection_suspected: suspected,
+            unclassified: false,
+            uncertain: false,
+            filters: Vec::new(),
+            promoted: false,
+        };
+        // Fields shared by every way this review can be stored.
+        let record = ReviewRecord {
+            item_id: &item_id,
+            feed_id: item.feed_id,
+            hash: &hash,
+            title: &item.title,
+            text: &text,
+            is_ad: false,
+            confidence: 0.0,
+            reason: "",
+            calibrated_confidence: None,
+            version: &version,
+            provider: None,
+            fewshot_ids: None,
+            link_features: link_features.as_deref(),
+            injection_suspected: suspected,
+            language: vars.language.as_deref(),
+            interest: None,
+            usage: Default::default(),
+            cost: 0.0,
+        };
+
+        let rule_input = RuleInput {
+            links: &vars.link_features,
+            text: &text,
+            language: vars.language.as_deref(),
+        };
+        if let Some(hit) = rules::evaluate(&self.cfg.rules, rule_input) {
+            info!(item_id = %item.id, reason = %hit.reason, "rule_decided");
+            self.db
+                .save_review(&ReviewRecord {
+                    is_ad: hit.is_ad,
+                    confidence: 1.0,
+                    reason: &hit.reason,
+                    provider: Some(RULES_PROVIDER),
+                    ..record
+                })
+                .await?;
+            return Ok(reviewed(hit.is_ad));
+        }
+
+        let examples = ctx.fewshot_examples(&self.llm, item, &text);
+        let fewshot_ids = fewshot::example_ids(&examples);
+        let record = ReviewRecord {
+            fewshot_ids: fewshot_ids.as_deref(),
+            ..record
+        };
+        let examples = &examples[..];
+        let classify =
+            |v: PromptVars| async move { ctx.breaker.call(self.llm.classify(&v, examples)).await };
+        let classified = match input::prepare(&self.cfg.input, &vars.text) {
+            Ok(prepared) => classify_prepared(&vars, prepared, classify).await,
+            Err(e) => Err(e.into()),
+        };
+        let answer = match classified {
+            Ok(answer) => answer,
             Err(err) => {
-                if let Some(api_err) = err.downcast_ref::<OpenAiApiError>() {
-                    if api_err.status == StatusCode::BAD_REQUEST {
-                        let title_preview = truncate(&item.title, 120);
-                        let reason = format!("{} | title={}", api_err, title_preview);
-                        warn!(item_id = %item.id, status = %api_err.status, title = %title_preview, reason = %reason, "openai_bad_request_marked");
-                        self.db
-                            .save_review(&item_id, &hash, false, 0.0, &reason)
-                            .await?;
-                        return Ok(ProcessAction::Kept);
-                    }
+                if let Some(parse_err) = err.downcast_ref::<VerdictParseError>() {
+                    ctx.record_parse_failure();
+                    self.charge(ctx, &parse_err.model, "review", &parse_err.usage)
+                        .await?;
+                }
+                let api_err = err.downcast_ref::<OpenAiApiError>();
+                let oversized = match err.downcast_ref::<OversizedError>() {
+                    Some(e) => Some(e.tokens),
+                    None => api_err
+                        .filter(|e| input::is_context_overflow(e))
+                        .map(|_| estimate_tokens(&vars.text)),
+                };
+                // Stored as not-ad so the item is not retried every run.
+                if let Some(tokens) = oversized {
+                    let err = OversizedError { tokens };
+                    warn!(item_id = %item.id, tokens, "item_oversized");
+                    let reason = err.to_string();
+                    self.db
+                        .save_review(&ReviewRecord {
+                            reason: &reason,
+                            ..record
+                        })
+                        .await?;
+                    return Err(err.into());
+                }
+                if let Some(api_err) = api_err
+                    && api_err.status == StatusCode::BAD_REQUEST
+                {
+                    let title_preview = truncate(&item.title, 120);
+                    let reason = format!("{} | title={}", api_err, title_preview);
+                    warn!(item_id = %item.id, status = %api_err.status, title = %title_preview, reason = %reason, "openai_bad_request_marked");
+                    self.db
+                        .save_review(&ReviewRecord {
+                            reason: &reason,
+                            ..record
+                        })
+                        .await?;
+                    return Ok(Reviewed {
+                        unclassified: true,
+                        ..reviewed(false)
+                    });
                 }
                 return Err(err);
             }
         };
+        let answer = if suspected && self.cfg.injection.secondary_check {
+            let redacted = ctx.injection.redact(&vars);
+            let second = match input::prepare(&self.cfg.input, &redacted.text) {
+                Ok(prepared) => classify_prepared(&redacted, prepared, classify).await,
+                Err(e) => Err(e.into()),
+            };
+            match second {
+                Ok(second) => injection::stricter(answer, second),
+                Err(e) => {
+                    warn!(item_id = %item.id, error = %e, "injection_check_failed");
+                    answer
+                }
+            }
+        } else {
+            answer
+        };
+        ctx.record_parse(answer.parse);
+        let cost = self
+            .charge(ctx, &answer.model, "review", &answer.usage)
+            .await?;
+        let answered = answer.version(&version);
+        let res = answer.verdict;
         self.db
-            .save_review(&item_id, &hash, res.is_ad, res.confidence, &res.reason)
+            .save_review(&ReviewRecord {
+                version: &answered,
+                is_ad: res.is_ad,
+                confidence: res.confidence,
+                reason: &res.reason,
+                calibrated_confidence: policy
+                    .calibrations
+                    .get(&answer.model)
+                    .map(|c| c.apply(res.confidence)),
+                provider: Some(&answer.provider),
+                interest: res.interest,
+                usage: answer.usage,
+                cost,
+                ..record
+            })
             .await?;
 
-        if res.is_ad && res.confidence >= self.cfg.openai.threshold {
-            if self.cfg.dry_run {
-                warn!(id = item.id, "dry_run_ad_detected");
-                return Ok(ProcessAction::WouldAct);
-            } else {
-                if self.cfg.freshrss.delete_mode == "mark_read" {
-                    self.fr.mark_item_read(item.id).await?;
-                    return Ok(ProcessAction::MarkedRead);
-                } else if self.cfg.freshrss.delete_mode == "label" {
-                    if let Some(gr) = &self.gr {
-                        gr.add_label(item.id, &self.cfg.freshrss.spam_label).await?;
-                        self.fr.mark_item_read(item.id).await?;
-                        return Ok(ProcessAction::Labeled);
+        let mut filters = Vec::new();
+        let mut verdicts = Vec::new();
+        for (name, profile) in &self.cfg.filters {
+            let Some(verdict) = res.filters.get(name) else {
+                continue;
+            };
+            verdicts.push((name.as_str(), verdict.matched, verdict.confidence));
+            if verdict.matched && verdict.confidence >= profile.threshold {
+                debug!(item_id = %item.id, filter = %name, confidence = verdict.confidence, "filter_matched");
+                filters.push(name.clone());
+            }
+        }
+        self.db.save_filter_verdicts(&item_id, &verdicts).await?;
+        filters.sort();
+        // Suspected injections could talk their way into a star.
+        let promoted = !suspected
+            && res
+                .interest
+                .is_some_and(|i| i >= self.cfg.interest.threshold);
+        if promoted {
+            debug!(item_id = %item.id, interest = ?res.interest, "interest_promoted");
+        }
+
+        let language = vars.language.as_deref();
+        let model = &answer.model;
+        let flagged = policy.is_flagged(model, res.is_ad, res.confidence, language);
+        Ok(Reviewed {
+            uncertain: !flagged && policy.is_uncertain(model, res.is_ad, res.confidence, language),
+            filters,
+            promoted,
+            ..reviewed(flagged)
+        })
+    }
+
+    /// Classify with the shadow classifier, if configured, and store its verdict on the side.
+    /// Failures are only logged so the primary path is never affected.
+    async fn shadow_review(&self, item: &FeverItem, ctx: &RunContext) {
+        let Some(shadow) = &self.shadow else {
+            return;
+        };
+        let examples = ctx.fewshot_examples(shadow, item, &item_text(item));
+        let vars = ctx.prompt_vars(item);
+        let res = match input::prepare(&self.cfg.input, &vars.text) {
+            Ok(prepared) => {
+                let examples = &examples[..];
+                classify_prepared(&vars, prepared, |v| async move {
+                    shadow.classify(&v, examples).await
+                })
+                .await
+            }
+            Err(e) => Err(e.into()),
+        };
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                warn!(item_id = item.id, error = %e, "shadow_classify_error");
+                return;
+            }
+        };
+        let version = res.version(&self.version_of(shadow));
+        if let Err(e) = self.charge(ctx, &res.model, "shadow", &res.usage).await {
+            warn!(item_id = item.id, error = %e, "shadow_usage_save_error");
+        }
+        let res = res.verdict;
+        if let Err(e) = self
+            .db
+            .save_shadow_review(
+                &item.id.to_string(),
+                res.is_ad,
+                res.confidence,
+                &res.reason,
+                &version,
+            )
+            .await
+        {
+            warn!(item_id = item.id, error = %e, "shadow_save_error");
+        }
+    }
+
+    /// Version of a classifier, including the settings outside `[openai]` that shape its prompt.
+    fn version_of(&self, llm: &OpenAiClient) -> ClassifierVersion {
+        let mut version = llm.version();
+        if llm.takes_examples()
+            && let Some(fewshot) = self.cfg.fewshot.fingerprint()
+        {
+            version = version.with_setting("fewshot", fewshot);
+        }
+        if llm.vision()
+            && let Some(images) = self.cfg.images.fingerprint()
+        {
+            version = version.with_setting("images", images);
+        }
+        version
+    }
+
+    /// Price an LLM call, add it to the usage ledger and the run totals. Returns its cost.
+    async fn charge(
+        &self,
+        ctx: &RunContext,
+        model: &str,
+        purpose: &str,
+        usage: &TokenUsage,
+    ) -> Result<f64> {
+        let cost = self
+            .cfg
+            .pricing
+            .get(model)
+            .map(|p| usage.cost(p))
+            .unwrap_or(0.0);
+        ctx.add_usage(usage, cost);
+        self.db.record_usage(model, purpose, usage, cost).await?;
+        Ok(cost)
+    }
+
+    /// Rough cost of reviewing `item`, shadow call included, priced as the primary model.
+    fn estimated_cost(&self, item: &FeverItem) -> f64 {
+        let Some(price) = self.cfg.pricing.get(&self.cfg.openai.model) else {
+            return 0.0;
+        };
+        let usage = TokenUsage {
+            prompt_tokens: estimate_tokens(&item_text(item)) + PROMPT_OVERHEAD_TOKENS,
+            completion_tokens: self.cfg.openai.max_tokens.unwrap_or(256) as u64,
+            cached_tokens: 0,
+        };
+        let calls = if self.shadow.is_some() { 2.0 } else { 1.0 };
+        usage.cost(price) * calls
+    }
+
+    /// Apply the configured `delete_mode` to an item classified as ad.
+    async fn apply_action(&self, item: &FeverItem) -> Result<ProcessAction> {
+        if self.cfg.dry_run {
+            warn!(id = item.id, "dry_run_ad_detected");
+            return Ok(ProcessAction::WouldAct);
+        }
+        if self.cfg.freshrss.delete_mode == "mark_read" {
+            self.fr.mark_item_read(item.id).await?;
+            return Ok(ProcessAction::MarkedRead);
+        } else if self.cfg.freshrss.delete_mode == "label" {
+            if let Some(gr) = &self.gr {
+                gr.add_label(item.id, &self.cfg.freshrss.spam_label).await?;
+                self.fr.mark_item_read(item.id).await?;
+                return Ok(ProcessAction::Labeled);
+            }
+        } else {
+            self.fr.delete_item_soft(item.id).await?;
+            return Ok(ProcessAction::Deleted);
+        }
+        Ok(ProcessAction::Kept)
+    }
+
+    /// Mark an item whose content tries to instruct the classifier for the reader to check.
+    async fn label_suspected(&self, item: &FeverItem) -> Result<ProcessAction> {
+        if self.cfg.dry_run {
+            warn!(id = item.id, "dry_run_injection_detected");
+            return Ok(ProcessAction::WouldAct);
+        }
+        match &self.gr {
+            Some(gr) => {
+                gr.add_label(item.id, &self.cfg.injection.label).await?;
+                Ok(ProcessAction::InjectionLabeled)
+            }
+            None => Ok(ProcessAction::Kept),
+        }
+    }
+
+    /// Label an ad verdict in the grey zone for the reader to decide; the item stays unread.
+    async fn label_uncertain(&self, item: &FeverItem) -> Result<ProcessAction> {
+        if self.cfg.dry_run {
+            warn!(id = item.id, "dry_run_uncertain_detected");
+            return Ok(ProcessAction::WouldAct);
+        }
+        match &self.gr {
+            Some(gr) => {
+                gr.add_label(item.id, &self.cfg.grey_zone.label).await?;
+                Ok(ProcessAction::ReviewLabeled)
+            }
+            None => Ok(ProcessAction::Kept),
+        }
+    }
+
+    /// Record the reader's decisions on grey-zone items as feedback. Removing the label
+    /// decides the item: an ad if it then carries `spam_label`, otherwise not an ad.
+    async fn sync_review_labels(&self) -> Result<()> {
+        let Some(gr) = &self.gr else {
+            return Ok(());
+        };
+        let pending = self
+            .db
+            .reviews_awaiting_feedback(ProcessAction::ReviewLabeled.as_str())
+            .await?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let labeled = gr.labeled_item_ids(&self.cfg.grey_zone.label).await?;
+        let decided: Vec<i64> = pending
+            .iter()
+            .filter_map(|id| id.parse().ok())
+            .filter(|id| !labeled.contains(id))
+            .collect();
+        if decided.is_empty() {
+            return Ok(());
+        }
+        // Items purged from FreshRSS lost their label without a decision.
+        let mut existing = HashSet::new();
+        for chunk in decided.chunks(50) {
+            existing.extend(self.fr.get_items_by_ids(chunk).await?.iter().map(|i| i.id));
+        }
+        let ads = gr.labeled_item_ids(&self.cfg.freshrss.spam_label).await?;
+        for id in decided.into_iter().filter(|id| existing.contains(id)) {
+            let is_ad = ads.contains(&id);
+            self.db
+                .save_feedback(&id.to_string(), is_ad, REVIEW_LABEL_SOURCE)
+                .await?;
+            info!(item_id = id, is_ad, "review_label_feedback");
+        }
+        Ok(())
+    }
+
+    /// Take the actions of the filter profiles an item matched. Labels are added for
+    /// every profile; the item is marked read once if any profile asks for it.
+    async fn apply_filters(&self, item: &FeverItem, matched: &[String]) -> Result<ProcessAction> {
+        if self.cfg.dry_run {
+            warn!(id = item.id, filters = %matched.join(","), "dry_run_filter_matched");
+            return Ok(ProcessAction::WouldAct);
+        }
+        let mut applied = Vec::new();
+        let mut mark_read = false;
+        for name in matched {
+            let Some(profile) = self.cfg.filters.get(name) else {
+                continue;
+            };
+            let mut acted = false;
+            if matches!(
+                profile.action,
+                FilterAction::Label | FilterAction::LabelRead
+            ) && let Some(gr) = &self.gr
+            {
+                gr.add_label(item.id, profile.label.as_deref().unwrap_or(name))
+                    .await?;
+                acted = true;
+            }
+            if matches!(
+                profile.action,
+                FilterAction::LabelRead | FilterAction::MarkRead
+            ) {
+                mark_read = true;
+                acted = true;
+            }
+            if acted {
+                applied.push(name.clone());
+            }
+        }
+        if mark_read {
+            self.fr.mark_item_read(item.id).await?;
+        }
+        if applied.is_empty() {
+            return Ok(ProcessAction::Kept);
+        }
+        self.db
+            .mark_filters_applied(&item.id.to_string(), &applied)
+            .await?;
+        Ok(ProcessAction::Filtered)
+    }
+
+    /// Star or label an item that scored above the interest threshold. Without GReader
+    /// credentials, starring falls back to Fever's `saved` state.
+    async fn promote(&self, item: &FeverItem) -> Result<ProcessAction> {
+        if self.cfg.dry_run {
+            warn!(id = item.id, "dry_run_promote_detected");
+            return Ok(ProcessAction::WouldAct);
+        }
+        let interest = &self.cfg.interest;
+        match (interest.action, &self.gr) {
+            (InterestAction::Star, Some(gr)) => gr.star(item.id).await?,
+            (InterestAction::Star, None) => self.fr.mark_item_saved(item.id).await?,
+            (InterestAction::Label, Some(gr)) => gr.add_label(item.id, &interest.label).await?,
+            (InterestAction::Label, None) => {
+                warn!(id = item.id, "promote_label_without_greader");
+                return Ok(ProcessAction::Kept);
+            }
+        }
+        Ok(ProcessAction::Promoted)
+    }
+
+    /// Undo an action previously taken on an item that is no longer classified as ad, or
+    /// the actions of the `dropped` filter profiles it no longer matches. The item stays
+    /// read while a profile whose action stays in place (`kept`) marks it read.
+    async fn revert_action(
+        &self,
+        item: &FeverItem,
+        previous: &str,
+        dropped: &[String],
+        kept: &[String],
+    ) -> Result<ProcessAction> {
+        if self.cfg.dry_run {
+            warn!(id = item.id, previous, "dry_run_revert_detected");
+            return Ok(ProcessAction::WouldAct);
+        }
+        if previous == ProcessAction::Filtered.as_str() {
+            let marks_read = |name: &String| {
+                self.cfg.filters.get(name).is_some_and(|p| {
+                    matches!(p.action, FilterAction::LabelRead | FilterAction::MarkRead)
+                })
+            };
+            for name in dropped {
+                let Some(profile) = self.cfg.filters.get(name) else {
+                    continue;
+                };
+                if matches!(
+                    profile.action,
+                    FilterAction::Label | FilterAction::LabelRead
+                ) && let Some(gr) = &self.gr
+                {
+                    gr.remove_label(item.id, profile.label.as_deref().unwrap_or(name))
+                        .await?;
+                }
+            }
+            if dropped.iter().any(marks_read) && !kept.iter().any(marks_read) {
+                self.fr.mark_item_unread(item.id).await?;
+            }
+            return Ok(ProcessAction::Reverted);
+        }
+        if previous == ProcessAction::Labeled.as_str()
+            && let Some(gr) = &self.gr
+        {
+            gr.remove_label(item.id, &self.cfg.freshrss.spam_label)
+                .await?;
+        }
+        self.fr.mark_item_unread(item.id).await?;
+        Ok(ProcessAction::Reverted)
+    }
+
+    /// Action name `apply_action` would record for the current `delete_mode`.
+    fn configured_action(&self) -> ProcessAction {
+        match self.cfg.freshrss.delete_mode.as_str() {
+            "mark_read" => ProcessAction::MarkedRead,
+            "label" if self.gr.is_some() => ProcessAction::Labeled,
+            "label" => ProcessAction::Kept,
+            _ => ProcessAction::Deleted,
+        }
+    }
+
+    /// Re-run items whose review was produced by an older prompt, model or setting.
+    #[instrument(skip(self), name = "reclassify")]
+    pub async fn reclassify(&self, opts: ReclassifyOptions) -> Result<()> {
+        let ctx = RunContext::load(self).await?;
+        let ctx = &ctx;
+        let policy = &ctx.policy;
+        let version = self.version_of(&self.llm);
+        let unread: Option<HashSet<String>> = if opts.unread_only {
+            Some(
+                self.fr
+                    .get_unread_item_ids()
+                    .await?
+                    .into_iter()
+                    .map(|id| id.to_string())
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        self.redecide(&version, policy, unread.as_ref()).await?;
+
+        let mut stale = self.db.stale_reviews(&version).await?;
+        let stale_total = stale.len();
+        if let Some(unread) = &unread {
+            stale.retain(|r| unread.contains(&r.item_id));
+        }
+        if let Some(margin) = opts.grey_zone {
+            stale.retain(|r| {
+                let model = r.model.as_deref().unwrap_or_default();
+                (policy.calibrate(model, r.confidence)
+                    - policy.threshold_for(model, r.language.as_deref()))
+                .abs()
+                    <= margin
+            });
+        }
+        if let Some(limit) = opts.limit {
+            stale.truncate(limit);
+        }
+        info!(
+            stale = stale_total,
+            selected = stale.len(),
+            "reclassify_candidates"
+        );
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<i64> = stale
+            .iter()
+            .filter_map(|r| r.item_id.parse().ok())
+            .collect();
+        let mut items: Vec<FeverItem> = Vec::new();
+        for chunk in ids.chunks(50) {
+            let mut got = self.fr.get_items_by_ids(chunk).await?;
+            items.append(&mut got);
+        }
+        let missing = stale.len().saturating_sub(items.len());
+        if missing > 0 {
+            warn!(missing, "reclassify_items_missing_in_freshrss");
+        }
+
+        let previous: HashMap<String, StoredReview> =
+            stale.into_iter().map(|r| (r.item_id.clone(), r)).collect();
+
+        let pb = ProgressBar::new(items.len() as u64);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("重新分类 {pos}/{len} [{bar:40.cyan/blue}] {percent}% | {msg}")
+                .expect("valid template")
+                .progress_chars("=>-"),
+        );
+
+        let pb_c = pb.clone();
+        let processed = stream::iter(items.into_iter())
+            .map(move |item| {
+                let pb = pb_c.clone();
+                let this = self.clone();
+                let prev = previous.get(&item.id.to_string()).cloned();
+                async move {
+                    let title = item.title.clone();
+                    let res = this.reclassify_item(item, prev, ctx).await;
+                    pb.inc(1);
+                    match &res {
+                        Ok(action) => {
+                            pb.set_message(format!("{} · {}", action, truncate(&title, 60)));
+                        }
+                        Err(e) => {
+                            let error_msg = format!("{}", e.to_string().yellow());
+                            pb.suspend(|| {
+                                warn!("{} 重新分类出错: {}", "[!]".yellow(), error_msg);
+                            });
+                        }
+                    }
+                    res
+                }
+            })
+            .buffer_unordered(5)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut counts = ActionCounts::default();
+        for a in processed.iter().flatten() {
+            counts.record(a);
+        }
+        let stats = ctx.stats();
+        pb.finish_with_message(format!(
+            "完成 | 未变化={} 新处理={} 待确认={} 主题过滤={} 推荐={} 已撤销={} 预演={} 推迟={} 中止={} 超长={} | 费用=${:.4}{}",
+            counts.unchanged,
+            counts.marked_read + counts.labeled + counts.deleted,
+            counts.review_labeled,
+            counts.filtered,
+            counts.promoted,
+            counts.reverted,
+            counts.would_act,
+            counts.deferred,
+            counts.aborted,
+            counts.oversized,
+            stats.cost,
+            ctx.budget_summary(stats.cost),
+        ));
+        Ok(())
+    }
+
+    /// Take the ad decision again for up-to-date reviews whose stored action no longer
+    /// follows from it, as after a threshold change. Works from the stored confidence
+    /// without calling the classifier.
+    async fn redecide(
+        &self,
+        version: &ClassifierVersion,
+        policy: &DecisionPolicy,
+        unread: Option<&HashSet<String>>,
+    ) -> Result<()> {
+        let flagged = |r: &StoredReview| {
+            policy.is_flagged(
+                r.model.as_deref().unwrap_or_default(),
+                r.is_ad,
+                r.confidence,
+                r.language.as_deref(),
+            ) || (r.injection_suspected == Some(true)
+                && self.cfg.injection.action == InjectionAction::Ad)
+        };
+        let changed: HashMap<i64, StoredReview> = self
+            .db
+            .current_reviews(version)
+            .await?
+            .into_iter()
+            .filter(|r| unread.is_none_or(|u| u.contains(&r.item_id)))
+            .filter(|r| {
+                r.action
+                    .as_deref()
+                    .is_some_and(|a| flagged(r) != ProcessAction::is_ad_action(a))
+            })
+            .filter_map(|r| Some((r.item_id.parse().ok()?, r)))
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<i64> = changed.keys().copied().collect();
+        let mut counts = ActionCounts::default();
+        for chunk in ids.chunks(50) {
+            for item in self.fr.get_items_by_ids(chunk).await? {
+                let Some(review) = changed.get(&item.id) else {
+                    continue;
+                };
+                let prev = review.action.clone().unwrap_or_default();
+                let action = if flagged(review) {
+                    if prev == ProcessAction::ReviewLabeled.as_str()
+                        && !self.cfg.dry_run
+                        && let Some(gr) = &self.gr
+                    {
+                        gr.remove_label(item.id, &self.cfg.grey_zone.label).await?;
                     }
+                    self.apply_action(&item).await?
                 } else {
-                    self.fr.delete_item_soft(item.id).await?;
-                    return Ok(ProcessAction::Deleted);
+                    self.revert_action(&item, &prev, &[], &[]).await?
+                };
+                let stored = match action {
+                    ProcessAction::Reverted => ProcessAction::Kept.as_str().to_string(),
+                    ProcessAction::WouldAct => prev,
+                    ref a => a.as_str().to_string(),
+                };
+                self.db
+                    .set_review_action(&item.id.to_string(), &stored)
+                    .await?;
+                counts.record(&action);
+            }
+        }
+        info!(
+            changed = changed.len(),
+            acted = counts.marked_read + counts.labeled + counts.deleted,
+            reverted = counts.reverted,
+            would_act = counts.would_act,
+            "reclassify_redecided"
+        );
+        Ok(())
+    }
+
+    #[instrument(name = "Reclassifying content", skip(self, item, prev, ctx), fields(item_id = item.id, title = %item.title))]
+    async fn reclassify_item(
+        &self,
+        item: FeverItem,
+        prev: Option<StoredReview>,
+        ctx: &RunContext,
+    ) -> Result<ProcessAction> {
+        let Some(_reservation) = ctx.reserve(self.estimated_cost(&item)) else {
+            return Ok(ProcessAction::Deferred);
+        };
+        if ctx.breaker.trip().is_some() {
+            return Ok(ProcessAction::Aborted);
+        }
+        let item = ctx.with_full_text(item).await;
+        ctx.resolve_links(&item).await;
+        ctx.load_images(&item).await;
+        let policy = &ctx.policy;
+        // Reviews written before actions were recorded are assumed to have been acted on
+        // if they would have crossed the current threshold.
+        let mut prev_action = prev
+            .and_then(|p| {
+                p.action.or_else(|| {
+                    let acted = !self.cfg.dry_run
+                        && policy.is_flagged(
+                            p.model.as_deref().unwrap_or_default(),
+                            p.is_ad,
+                            p.confidence,
+                            p.language.as_deref(),
+                        );
+                    acted.then(|| self.configured_action().as_str().to_string())
+                })
+            })
+            .unwrap_or_else(|| ProcessAction::Kept.as_str().to_string());
+        let was_acted = ProcessAction::is_acted(&prev_action);
+        let was_filtered = prev_action == ProcessAction::Filtered.as_str();
+        // Read before the review below replaces the filter verdicts.
+        let prev_filters = if was_filtered {
+            self.db.applied_filters(&item.id.to_string()).await?
+        } else {
+            Vec::new()
+        };
+
+        let reviewed = self.review(&item, ctx).await;
+        ctx.forget_images(&item);
+        let reviewed = match reviewed {
+            Err(e) if e.is::<CircuitOpenError>() => return Ok(ProcessAction::Aborted),
+            Err(e) if e.is::<OversizedError>() => {
+                self.db
+                    .set_review_action(&item.id.to_string(), ProcessAction::Oversized.as_str())
+                    .await?;
+                return Ok(ProcessAction::Oversized);
+            }
+            res => res?,
+        };
+        // A request the classifier refused says nothing about the item: keep what was done.
+        if reviewed.unclassified {
+            self.db
+                .set_review_action(&item.id.to_string(), &prev_action)
+                .await?;
+            return Ok(ProcessAction::Unchanged);
+        }
+        // The grey-zone label goes once the verdict has left the grey zone.
+        if prev_action == ProcessAction::ReviewLabeled.as_str()
+            && !reviewed.uncertain
+            && !self.cfg.dry_run
+            && let Some(gr) = &self.gr
+        {
+            gr.remove_label(item.id, &self.cfg.grey_zone.label).await?;
+            prev_action = ProcessAction::Kept.as_str().to_string();
+        }
+        let (action, stored) = match (reviewed.flagged, was_acted) {
+            (true, acted) if !acted || was_filtered => {
+                let action = self.apply_action(&item).await?;
+                let stored = action.as_str().to_string();
+                (action, stored)
+            }
+            (false, true) if was_filtered => {
+                let (kept, dropped): (Vec<String>, Vec<String>) = prev_filters
+                    .into_iter()
+                    .partition(|f| reviewed.filters.contains(f));
+                // The new verdicts start out unapplied; what stays in place still is.
+                self.db
+                    .mark_filters_applied(&item.id.to_string(), &kept)
+                    .await?;
+                if dropped.is_empty() {
+                    (ProcessAction::Unchanged, prev_action)
+                } else {
+                    let action = self
+                        .revert_action(&item, &prev_action, &dropped, &kept)
+                        .await?;
+                    if !matches!(action, ProcessAction::Reverted) {
+                        self.db
+                            .mark_filters_applied(&item.id.to_string(), &dropped)
+                            .await?;
+                    }
+                    let stored = match action {
+                        ProcessAction::Reverted if kept.is_empty() => {
+                            ProcessAction::Kept.as_str().to_string()
+                        }
+                        _ => prev_action,
+                    };
+                    (action, stored)
                 }
             }
+            (false, true) => {
+                let action = self.revert_action(&item, &prev_action, &[], &[]).await?;
+                let stored = match action {
+                    ProcessAction::Reverted => ProcessAction::Kept.as_str().to_string(),
+                    _ => prev_action,
+                };
+                (action, stored)
+            }
+            (false, false)
+                if reviewed.uncertain && prev_action != ProcessAction::ReviewLabeled.as_str() =>
+            {
+                let action = self.label_uncertain(&item).await?;
+                let stored = action.as_str().to_string();
+                (action, stored)
+            }
+            (false, false) if !reviewed.filters.is_empty() => {
+                let action = self.apply_filters(&item, &reviewed.filters).await?;
+                let stored = action.as_str().to_string();
+                (action, stored)
+            }
+            (false, false)
+                if reviewed.promoted
+                    && reviewed.filters.is_empty()
+                    && prev_action != ProcessAction::Promoted.as_str() =>
+            {
+                let action = self.promote(&item).await?;
+                let stored = action.as_str().to_string();
+                (action, stored)
+            }
+            _ => (ProcessAction::Unchanged, prev_action),
+        };
+        self.db
+            .set_review_action(&item.id.to_string(), &stored)
+            .await?;
+        Ok(action)
+    }
+}
+
+/// Outcome of classifying one item.
+struct Reviewed {
+    /// Crossed the ad threshold, or handled like an ad because of `injection.action`
+    flagged: bool,
+    /// Not flagged, but an ad verdict above `grey_zone.threshold`
+    uncertain: bool,
+    injection_suspected: bool,
+    /// The classifier refused the request; stored as not-ad without a verdict
+    unclassified: bool,
+    /// Filter profiles matched above their threshold
+    filters: Vec<String>,
+    /// Scored at or above the interest threshold
+    promoted: bool,
+}
+
+/// State shared by every item of one run.
+struct RunContext {
+    policy: DecisionPolicy,
+    budget: Budget,
+    breaker: CircuitBreaker,
+    feeds: HashMap<i64, FeverFeed>,
+    /// `(ads, reviewed)` per feed as of the start of the run
+    feed_ad_rates: HashMap<i64, (u64, u64)>,
+    fewshot: Option<FewShotPool>,
+    link_lists: LinkLists,
+    adblock: Arc<Engine>,
+    injection: Detector,
+    fulltext: Option<FullText>,
+    resolver: Option<Resolver>,
+    /// Links followed this run, by URL
+    redirects: Mutex<HashMap<String, Url>>,
+    images: Option<ImageFetcher>,
+    /// Image inputs of the items being reviewed, by item id
+    image_inputs: Mutex<HashMap<i64, Vec<ImageInput>>>,
+    stats: Mutex<RunStats>,
+}
+
+/// Budget set aside for a review in flight, returned when dropped.
+struct Reservation<'a> {
+    ctx: &'a RunContext,
+    amount: f64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut stats) = self.ctx.stats.lock() {
+            stats.reserved -= self.amount;
         }
-        Ok(ProcessAction::Kept)
     }
 }
 
+#[derive(Debug, Clone, Default)]
+struct RunStats {
+    usage: TokenUsage,
+    cost: f64,
+    /// Estimated cost of reviews in flight
+    reserved: f64,
+    budget_hit: Option<BudgetCap>,
+    lenient_parses: u64,
+    repairs: u64,
+    parse_failures: u64,
+    injections: u64,
+}
+
+impl RunContext {
+    async fn load(p: &Processor) -> Result<Self> {
+        let half_open =
+            p.db.last_run_note()
+                .await?
+                .is_some_and(|n| n.contains(OPEN_NOTE));
+        // Feed titles only enrich prompts, so a failure here must not stop the run.
+        let feeds = p.fr.get_feeds().await.unwrap_or_else(|e| {
+            warn!(error = %e, "feeds_fetch_failed");
+            HashMap::new()
+        });
+        if p.cfg.fewshot.enabled && !p.llm.takes_examples() {
+            warn!("fewshot_skipped_with_filters_or_interest");
+        }
+        let fewshot = if p.cfg.fewshot.enabled {
+            Some(FewShotPool::load(&p.cfg.fewshot, &p.db).await?)
+        } else {
+            None
+        };
+        let link_lists = LinkLists::load(&p.cfg.links)?;
+        let resolver = p
+            .cfg
+            .resolver
+            .enabled
+            .then(|| {
+                Resolver::new(
+                    &p.cfg.resolver,
+                    link_lists.redirector_domains(),
+                    &p.cfg.freshrss.user_agent,
+                    p.db.clone(),
+                )
+            })
+            .transpose()?;
+        Ok(Self {
+            policy: DecisionPolicy::load(&p.cfg, &p.db).await?,
+            budget: Budget::load(&p.cfg.budget, &p.db).await?,
+            breaker: CircuitBreaker::new(p.cfg.breaker.failure_threshold, half_open),
+            feeds,
+            feed_ad_rates: p.db.feed_ad_rates().await?,
+            fewshot,
+            link_lists,
+            adblock: p.adblock.current(),
+            injection: Detector::new(&p.cfg.injection),
+            fulltext: p
+                .cfg
+                .fulltext
+                .enabled
+                .then(|| FullText::new(&p.cfg.fulltext, &p.cfg.freshrss.user_agent, p.db.clone()))
+                .transpose()?,
+            resolver,
+            redirects: Mutex::new(HashMap::new()),
+            // Nothing to download for when no classifier takes images.
+            images: (p.cfg.images.enabled
+                && (p.llm.vision() || p.shadow.as_ref().is_some_and(|s| s.vision())))
+            .then(|| ImageFetcher::new(&p.cfg.images, &p.cfg.freshrss.user_agent))
+            .transpose()?,
+            image_inputs: Mutex::new(HashMap::new()),
+            stats: Mutex::new(RunStats::default()),
+        })
+    }
+
+    /// Set `estimate` aside for a review about to start, unless spend so far plus the
+    /// reviews already in flight would reach a cap. The first hit is kept for the run summary.
+    fn reserve(&self, estimate: f64) -> Option<Reservation<'_>> {
+        let mut stats = self.stats.lock().expect("run stats lock");
+        let hit = self
+            .budget
+            .exhausted(stats.cost + stats.reserved + estimate);
+        if let Some(cap) = hit {
+            if stats.budget_hit.is_none() {
+                warn!(cap = %cap, "budget_exhausted_deferring_items");
+                stats.budget_hit = Some(cap);
+            }
+            return None;
+        }
+        stats.reserved += estimate;
+        Some(Reservation {
+            ctx: self,
+            amount: estimate,
+        })
+    }
+
+    fn add_usage(&self, usage: &TokenUsage, cost: f64) {
+        let mut stats = self.stats.lock().expect("run stats lock");
+        stats.usage.add(usage);
+        stats.cost += cost;
+    }
+
+    /// The item with its linked article as HTML when the feed only carries a teaser.
+    /// The teaser is kept as `content` so nothing the feed said is lost.
+    async fn with_full_text(&self, mut item: FeverItem) -> FeverItem {
+        let Some(fulltext) = &self.fulltext else {
+            return item;
+        };
+        let teaser = item_body(&item, &item_html(&item));
+        let Some(url) = fulltext.wants(&item, teaser.chars().count()) else {
+            return item;
+        };
+        if let Some(article) = fulltext.article(&url).await {
+            debug!(item_id = item.id, url = %url, "fulltext_applied");
+            if item.content.as_deref().is_none_or(|c| c.trim().is_empty()) {
+                item.content = Some(teaser);
+            }
+            item.html = Some(article);
+        }
+        item
+    }
+
+    /// Follow the shortened and redirecting links of an item, remembering where they end
+    /// for `prompt_vars`.
+    async fn resolve_links(&self, item: &FeverItem) {
+        let Some(resolver) = &self.resolver else {
+            return;
+        };
+        let urls: Vec<Url> = {
+            let known = self.redirects.lock().expect("redirects lock");
+            resolver
+                .candidates(&item_html(item), item.url.as_deref())
+                .into_iter()
+                .filter(|u| !known.contains_key(u.as_str()))
+                .collect()
+        };
+        let ends = future::join_all(urls.iter().map(|u| resolver.destination(u))).await;
+        let mut known = self.redirects.lock().expect("redirects lock");
+        for (url, end) in urls.into_iter().zip(ends) {
+            if let Some(end) = end {
+                known.insert(url.to_string(), end);
+            }
+        }
+    }
+
+    /// Download the lead images of an item for `prompt_vars`, if its feed sends images.
+    async fn load_images(&self, item: &FeverItem) {
+        let Some(fetcher) = self.images.as_ref().filter(|f| f.wants(item)) else {
+            return;
+        };
+        let urls = fetcher.candidates(&item_html(item), item.url.as_deref());
+        let images = fetcher.load(&urls).await;
+        if !images.is_empty() {
+            debug!(item_id = item.id, images = images.len(), "images_attached");
+            self.image_inputs
+                .lock()
+                .expect("image inputs lock")
+                .insert(item.id, images);
+        }
+    }
+
+    fn forget_images(&self, item: &FeverItem) {
+        self.image_inputs
+            .lock()
+            .expect("image inputs lock")
+            .remove(&item.id);
+    }
+
+    fn prompt_vars(&self, item: &FeverItem) -> PromptVars {
+        let html = item_html(item);
+        let mut link_features = {
+            let redirects = self.redirects.lock().expect("redirects lock");
+            self.link_lists
+                .analyze(&html, item.url.as_deref(), &redirects)
+        };
+        link_features.blocked = self.adblock.check(&html, item.url.as_deref());
+        PromptVars {
+            title: item.title.clone(),
+            author: item.author.clone(),
+            url: item.url.clone(),
+            feed_title: item
+                .feed_id
+                .and_then(|id| self.feeds.get(&id))
+                .map(|f| f.title.clone()),
+            published: item.created_on_time,
+            text: item_body(item, &html),
+            links: html.links,
+            images: html.image_alts,
+            iframes: html.iframes,
+            link_features,
+            feed_ad_rate: item
+                .feed_id
+                .and_then(|id| self.feed_ad_rates.get(&id).copied()),
+            language: language::detect(&item_text(item)),
+            image_inputs: self
+                .image_inputs
+                .lock()
+                .expect("image inputs lock")
+                .get(&item.id)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Few-shot examples for an item; `text` is the text stored with its review.
+    /// Examples for `llm` to see before the item; none when it also judges filters or
+    /// interest, which the confirmed verdicts of the examples say nothing about.
+    fn fewshot_examples(
+        &self,
+        llm: &OpenAiClient,
+        item: &FeverItem,
+        text: &str,
+    ) -> Vec<FewShotExample> {
+        self.fewshot
+            .as_ref()
+            .filter(|_| llm.takes_examples())
+            .map(|pool| pool.select(&item.id.to_string(), item.feed_id, text, &self.feeds))
+            .unwrap_or_default()
+    }
+
+    fn record_injection(&self) {
+        self.stats.lock().expect("run stats lock").injections += 1;
+    }
+
+    fn record_parse(&self, parse: VerdictParse) {
+        let mut stats = self.stats.lock().expect("run stats lock");
+        match parse {
+            VerdictParse::Strict => {}
+            VerdictParse::Lenient => stats.lenient_parses += 1,
+            VerdictParse::Repaired => stats.repairs += 1,
+        }
+    }
+
+    fn record_parse_failure(&self) {
+        self.stats.lock().expect("run stats lock").parse_failures += 1;
+    }
+
+    fn stats(&self) -> RunStats {
+        self.stats.lock().expect("run stats lock").clone()
+    }
+
+    /// Spend against the configured caps, for progress output.
+    fn budget_summary(&self, run_cost: f64) -> String {
+        let mut out = String::new();
+        if let Some(cap) = self.budget.daily_cap() {
+            out.push_str(&format!(
+                " | 今日 ${:.2}/${:.2}",
+                self.budget.spent_day + run_cost,
+                cap
+            ));
+        }
+        if let Some(cap) = self.budget.monthly_cap() {
+            out.push_str(&format!(
+                " | 本月 ${:.2}/${:.2}",
+                self.budget.spent_month + run_cost,
+                cap
+            ));
+        }
+        out
+    }
+}
+
+/// Filters for the `reclassify` subcommand.
+#[derive(Debug, Default)]
+pub struct ReclassifyOptions {
+    pub unread_only: bool,
+    pub grey_zone: Option<f32>,
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug)]
 enum ProcessAction {
     SkippedExists,
@@ -242,6 +1497,54 @@ enum ProcessAction {
     Labeled,
     Deleted,
     WouldAct,
+    Unchanged,
+    Reverted,
+    Deferred,
+    Aborted,
+    Oversized,
+    InjectionLabeled,
+    ReviewLabeled,
+    Filtered,
+    Promoted,
+}
+
+impl ProcessAction {
+    /// Name stored in `reviews.action`.
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProcessAction::SkippedExists => "skipped_exists",
+            ProcessAction::Kept => "kept",
+            ProcessAction::MarkedRead => "marked_read",
+            ProcessAction::Labeled => "labeled",
+            ProcessAction::Deleted => "deleted",
+            ProcessAction::WouldAct => "would_act",
+            ProcessAction::Unchanged => "unchanged",
+            ProcessAction::Reverted => "reverted",
+            ProcessAction::Deferred => "deferred",
+            ProcessAction::Aborted => "aborted",
+            ProcessAction::Oversized => "oversized",
+            ProcessAction::InjectionLabeled => "injection_labeled",
+            ProcessAction::ReviewLabeled => "review_labeled",
+            ProcessAction::Filtered => "filtered",
+            ProcessAction::Promoted => "promoted",
+        }
+    }
+
+    /// Whether a stored action name means FreshRSS was modified.
+    fn is_acted(name: &str) -> bool {
+        Self::is_ad_action(name) || name == ProcessAction::Filtered.as_str()
+    }
+
+    /// Whether a stored action name is what `delete_mode` does to ads.
+    fn is_ad_action(name: &str) -> bool {
+        [
+            ProcessAction::MarkedRead,
+            ProcessAction::Labeled,
+            ProcessAction::Deleted,
+        ]
+        .iter()
+        .any(|a| a.as_str() == name)
+    }
 }
 
 impl Display for ProcessAction {
@@ -253,6 +1556,67 @@ impl Display for ProcessAction {
             ProcessAction::Labeled => write!(f, "打标签"),
             ProcessAction::Deleted => write!(f, "删除"),
             ProcessAction::WouldAct => write!(f, "预演(不改动)"),
+            ProcessAction::Unchanged => write!(f, "未变化"),
+            ProcessAction::Reverted => write!(f, "已撤销"),
+            ProcessAction::Deferred => write!(f, "推迟(预算已用尽)"),
+            ProcessAction::Aborted => write!(f, "中止(分类服务不可用)"),
+            ProcessAction::Oversized => write!(f, "超长(未分类)"),
+            ProcessAction::InjectionLabeled => write!(f, "疑似注入(已打标签)"),
+            ProcessAction::ReviewLabeled => write!(f, "待人工确认(已打标签)"),
+            ProcessAction::Filtered => write!(f, "主题过滤"),
+            ProcessAction::Promoted => write!(f, "推荐(加星/打标)"),
+        }
+    }
+}
+
+/// Classify prepared item text with `classify`, one call per chunk when it was split.
+async fn classify_prepared<F, Fut>(
+    vars: &PromptVars,
+    prepared: Prepared,
+    classify: F,
+) -> Result<Classification>
+where
+    F: Fn(PromptVars) -> Fut,
+    Fut: Future<Output = Result<Classification>>,
+{
+    match prepared {
+        Prepared::Whole(text) => {
+            classify(PromptVars {
+                text,
+                ..vars.clone()
+            })
+            .await
+        }
+        Prepared::Truncated(text) => {
+            debug!(title = %vars.title, "item_input_truncated");
+            classify(PromptVars {
+                text,
+                ..vars.clone()
+            })
+            .await
+        }
+        Prepared::Chunks(chunks) => {
+            let total = chunks.len();
+            debug!(title = %vars.title, chunks = total, "item_input_chunked");
+            let mut parts = Vec::with_capacity(total);
+            for (i, text) in chunks.into_iter().enumerate() {
+                let text = format!("[part {}/{}]\n{}", i + 1, total, text);
+                // Images are sent once, with the first part.
+                let image_inputs = if i == 0 {
+                    vars.image_inputs.clone()
+                } else {
+                    Vec::new()
+                };
+                parts.push(
+                    classify(PromptVars {
+                        text,
+                        image_inputs,
+                        ..vars.clone()
+                    })
+                    .await?,
+                );
+            }
+            input::combine(parts).ok_or_else(|| anyhow!("classify_no_chunks"))
         }
     }
 }
@@ -272,4 +1636,35 @@ struct ActionCounts {
     labeled: u64,
     deleted: u64,
     would_act: u64,
+    unchanged: u64,
+    reverted: u64,
+    deferred: u64,
+    aborted: u64,
+    oversized: u64,
+    injection_labeled: u64,
+    review_labeled: u64,
+    filtered: u64,
+    promoted: u64,
+}
+
+impl ActionCounts {
+    fn record(&mut self, action: &ProcessAction) {
+        match action {
+            ProcessAction::SkippedExists => self.skipped_exists += 1,
+            ProcessAction::Kept => self.kept += 1,
+            ProcessAction::MarkedRead => self.marked_read += 1,
+            ProcessAction::Labeled => self.labeled += 1,
+            ProcessAction::Deleted => self.deleted += 1,
+            ProcessAction::WouldAct => self.would_act += 1,
+            ProcessAction::Unchanged => self.unchanged += 1,
+            ProcessAction::Reverted => self.reverted += 1,
+            ProcessAction::Deferred => self.deferred += 1,
+            ProcessAction::Aborted => self.aborted += 1,
+            ProcessAction::Oversized => self.oversized += 1,
+            ProcessAction::InjectionLabeled => self.injection_labeled += 1,
+            ProcessAction::ReviewLabeled => self.review_labeled += 1,
+            ProcessAction::Filtered => self.filtered += 1,
+            ProcessAction::Promoted => self.promoted += 1,
+        }
+    }
 }
@@ -0,0 +1,450 @@
+use crate::images::ImageInput;
+use crate::openai_client::TokenUsage;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// Built-in request shapes for common providers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Chat completions with `temperature` and `max_tokens`
+    Openai,
+    /// o-series and other reasoning models: `max_completion_tokens`, `reasoning_effort`, no `temperature`
+    OpenaiReasoning,
+    /// Azure OpenAI deployments: `api-key` header and `api-version` query parameter
+    Azure,
+    Openrouter,
+    /// Self-hosted OpenAI-compatible servers; reasoning parsers put thoughts in `reasoning_content`
+    Vllm,
+}
+
+/// Endpoint family the request is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Api {
+    /// `/chat/completions` with `messages`
+    ChatCompletions,
+    /// `/responses` with `instructions`, `input` and a `text.format` JSON schema
+    Responses,
+}
+
+/// Which field carries the completion length limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenParam {
+    MaxTokens,
+    MaxCompletionTokens,
+    /// Do not send a limit
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `api-key: <key>`
+    ApiKeyHeader,
+    None,
+}
+
+/// Per-endpoint request settings. A preset supplies the defaults and any field set
+/// here overrides it. Without a preset one is picked from the model name and `api_base`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    #[serde(default)]
+    pub preset: Option<Preset>,
+    #[serde(default)]
+    pub api: Option<Api>,
+    #[serde(default)]
+    pub token_param: Option<TokenParam>,
+    #[serde(default)]
+    pub supports_temperature: Option<bool>,
+    /// Ask for JSON: `response_format` for chat completions, a `text.format` schema for responses
+    #[serde(default)]
+    pub json_mode: Option<bool>,
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    /// Accepts image inputs; `[images]` are not sent to endpoints without it
+    #[serde(default)]
+    pub vision: Option<bool>,
+    #[serde(default)]
+    pub auth: Option<AuthStyle>,
+    /// Request URL with `{api_base}`, `{model}` and `{api_version}` placeholders
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub api_version: Option<String>,
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+/// Fully resolved request shape for one endpoint.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub preset: Preset,
+    pub api: Api,
+    pub token_param: TokenParam,
+    pub supports_temperature: bool,
+    pub json_mode: bool,
+    pub reasoning_effort: Option<String>,
+    pub vision: bool,
+    pub auth: AuthStyle,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+const CHAT_URL: &str = "{api_base}/chat/completions";
+const RESPONSES_URL: &str = "{api_base}/responses";
+const AZURE_URL: &str =
+    "{api_base}/openai/deployments/{model}/chat/completions?api-version={api_version}";
+const AZURE_RESPONSES_URL: &str = "{api_base}/openai/responses?api-version={api_version}";
+const AZURE_API_VERSION: &str = "2024-10-21";
+
+impl Preset {
+    /// Guess the preset from the model name and endpoint.
+    pub fn detect(model: &str, api_base: &str) -> Self {
+        let model = model.rsplit('/').next().unwrap_or(model);
+        if api_base.contains(".openai.azure.com") {
+            Preset::Azure
+        } else if ["o1", "o3", "o4", "gpt-5"]
+            .iter()
+            .any(|p| model.starts_with(p))
+        {
+            Preset::OpenaiReasoning
+        } else if api_base.contains("openrouter.ai") {
+            Preset::Openrouter
+        } else {
+            Preset::Openai
+        }
+    }
+
+    fn defaults(self) -> Profile {
+        let mut p = Profile {
+            preset: self,
+            api: Api::ChatCompletions,
+            token_param: TokenParam::MaxTokens,
+            supports_temperature: true,
+            json_mode: true,
+            reasoning_effort: None,
+            vision: true,
+            auth: AuthStyle::Bearer,
+            url: CHAT_URL.into(),
+            headers: Vec::new(),
+        };
+        match self {
+            Preset::Openai => {}
+            // Both front many text-only models.
+            Preset::Openrouter | Preset::Vllm => p.vision = false,
+            Preset::OpenaiReasoning => {
+                p.token_param = TokenParam::MaxCompletionTokens;
+                p.supports_temperature = false;
+                p.reasoning_effort = Some("low".into());
+            }
+            Preset::Azure => {
+                p.auth = AuthStyle::ApiKeyHeader;
+                p.url = AZURE_URL.into();
+            }
+        }
+        p
+    }
+}
+
+impl Profile {
+    /// Resolve the profile for an endpoint from its optional overrides.
+    pub fn resolve(cfg: Option<&ProfileConfig>, model: &str, api_base: &str) -> Self {
+        let default_cfg = ProfileConfig::default();
+        let cfg = cfg.unwrap_or(&default_cfg);
+        let preset = cfg
+            .preset
+            .unwrap_or_else(|| Preset::detect(model, api_base));
+        let mut p = preset.defaults();
+        if let Some(v) = cfg.api {
+            p.api = v;
+        }
+        if p.api == Api::Responses {
+            p.url = match preset {
+                Preset::Azure => AZURE_RESPONSES_URL.into(),
+                _ => RESPONSES_URL.into(),
+            };
+        }
+        if let Some(v) = cfg.token_param {
+            p.token_param = v;
+        }
+        if let Some(v) = cfg.supports_temperature {
+            p.supports_temperature = v;
+        }
+        if let Some(v) = cfg.json_mode {
+            p.json_mode = v;
+        }
+        if cfg.reasoning_effort.is_some() {
+            p.reasoning_effort = cfg.reasoning_effort.clone();
+        }
+        if let Some(v) = cfg.vision {
+            p.vision = v;
+        }
+        if let Some(v) = cfg.auth {
+            p.auth = v;
+        }
+        let template = cfg.url.as_deref().unwrap_or(&p.url);
+        let api_version = cfg.api_version.as_deref().unwrap_or(AZURE_API_VERSION);
+        p.url = template
+            .replace("{api_base}", api_base.trim_end_matches('/'))
+            .replace("{model}", model)
+            .replace("{api_version}", api_version);
+        let mut headers: Vec<(String, String)> = cfg
+            .headers
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        headers.sort();
+        p.headers = headers;
+        p
+    }
+
+    /// Request body for a conversation of `(role, content)` turns after the system prompt.
+    /// `shape` lists the optional parts the structured-output schema must allow.
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_body(
+        &self,
+        model: &str,
+        system: &str,
+        turns: &[(&str, &str)],
+        images: &[ImageInput],
+        shape: &VerdictShape,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Value {
+        let images = if self.vision { images } else { &[] };
+        // Images go with the last turn, the item being classified.
+        let last = turns.len().saturating_sub(1);
+        let content = |i: usize, text: &str| {
+            if i != last || images.is_empty() {
+                return json!(text);
+            }
+            let mut parts = vec![match self.api {
+                Api::ChatCompletions => json!({ "type": "text", "text": text }),
+                Api::Responses => json!({ "type": "input_text", "text": text }),
+            }];
+            parts.extend(images.iter().map(|img| match self.api {
+                Api::ChatCompletions => json!({
+                    "type": "image_url",
+                    "image_url": { "url": img.url, "detail": img.detail },
+                }),
+                Api::Responses => json!({
+                    "type": "input_image",
+                    "image_url": img.url,
+                    "detail": img.detail,
+                }),
+            }));
+            json!(parts)
+        };
+        let messages = turns
+            .iter()
+            .enumerate()
+            .map(|(i, (role, text))| json!({ "role": role, "content": content(i, text) }));
+        let mut body = match self.api {
+            Api::ChatCompletions => {
+                let messages: Vec<Value> =
+                    std::iter::once(json!({ "role": "system", "content": system }))
+                        .chain(messages)
+                        .collect();
+                json!({ "model": model, "messages": messages })
+            }
+            Api::Responses => {
+                let input = match turns {
+                    [("user", text)] if images.is_empty() => json!(text),
+                    _ => json!(messages.collect::<Vec<_>>()),
+                };
+                json!({ "model": model, "instructions": system, "input": input })
+            }
+        };
+        if self.json_mode {
+            match self.api {
+                Api::ChatCompletions => {
+                    body["response_format"] = json!({ "type": "json_object" });
+                }
+                Api::Responses => {
+                    body["text"] = json!({ "format": {
+                        "type": "json_schema",
+                        "name": "ad_verdict",
+                        "strict": true,
+                        "schema": verdict_schema(shape),
+                    }});
+                }
+            }
+        }
+        if self.supports_temperature
+            && let Some(t) = temperature
+        {
+            body["temperature"] = json!(t);
+        }
+        if let Some(n) = max_tokens {
+            match (self.api, self.token_param) {
+                (_, TokenParam::None) => {}
+                (Api::Responses, _) => body["max_output_tokens"] = json!(n),
+                (Api::ChatCompletions, TokenParam::MaxTokens) => body["max_tokens"] = json!(n),
+                (Api::ChatCompletions, TokenParam::MaxCompletionTokens) => {
+                    body["max_completion_tokens"] = json!(n)
+                }
+            }
+        }
+        if let Some(effort) = &self.reasoning_effort {
+            match self.api {
+                Api::ChatCompletions => body["reasoning_effort"] = json!(effort),
+                Api::Responses => body["reasoning"] = json!({ "effort": effort }),
+            }
+        }
+        body
+    }
+
+    /// The text carrying the verdict.
+    pub fn extract_content(&self, v: &Value) -> String {
+        match self.api {
+            Api::ChatCompletions => extract_chat_content(v),
+            Api::Responses => extract_output_text(v),
+        }
+    }
+
+    pub fn extract_usage(&self, v: &Value) -> TokenUsage {
+        let u = &v["usage"];
+        let (prompt, completion, details) = match self.api {
+            Api::ChatCompletions => (
+                "prompt_tokens",
+                "completion_tokens",
+                "prompt_tokens_details",
+            ),
+            Api::Responses => ("input_tokens", "output_tokens", "input_tokens_details"),
+        };
+        TokenUsage {
+            prompt_tokens: u[prompt].as_u64().unwrap_or(0),
+            completion_tokens: u[completion].as_u64().unwrap_or(0),
+            cached_tokens: u[details]["cached_tokens"].as_u64().unwrap_or(0),
+        }
+    }
+
+    /// Attach credentials and extra headers.
+    pub fn authorize(
+        &self,
+        mut req: reqwest::RequestBuilder,
+        api_key: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        if let Some(key) = api_key.filter(|k| !k.is_empty()) {
+            req = match self.auth {
+                AuthStyle::Bearer => req.bearer_auth(key),
+                AuthStyle::ApiKeyHeader => req.header("api-key", key),
+                AuthStyle::None => req,
+            };
+        }
+        for (k, v) in &self.headers {
+            req = req.header(k, v);
+        }
+        req
+    }
+}
+
+/// Optional parts of the verdict requested besides the ad judgement.
+pub struct VerdictShape<'a> {
+    /// Names of the `[filters]` profiles
+    pub filters: &'a [&'a str],
+    /// Whether an `interest` score is requested
+    pub interest: bool,
+}
+
+/// JSON schema of `ClassifierResponse` for structured outputs.
+fn verdict_schema(shape: &VerdictShape) -> Value {
+    let filters = shape.filters;
+    let mut schema = json!({
+        "type": "object",
+        "properties": {
+            "is_ad": { "type": "boolean" },
+            "confidence": { "type": "number" },
+            "reason": { "type": "string" },
+        },
+        "required": ["is_ad", "confidence", "reason"],
+        "additionalProperties": false,
+    });
+    if !filters.is_empty() {
+        let verdict = json!({
+            "type": "object",
+            "properties": {
+                "match": { "type": "boolean" },
+                "confidence": { "type": "number" },
+            },
+            "required": ["match", "confidence"],
+            "additionalProperties": false,
+        });
+        let properties: serde_json::Map<String, Value> = filters
+            .iter()
+            .map(|name| (name.to_string(), verdict.clone()))
+            .collect();
+        schema["properties"]["filters"] = json!({
+            "type": "object",
+            "properties": properties,
+            "required": filters,
+            "additionalProperties": false,
+        });
+        push_required(&mut schema, "filters");
+    }
+    if shape.interest {
+        schema["properties"]["interest"] = json!({ "type": "number" });
+        push_required(&mut schema, "interest");
+    }
+    schema
+}
+
+fn push_required(schema: &mut Value, key: &str) {
+    if let Some(required) = schema["required"].as_array_mut() {
+        required.push(json!(key));
+    }
+}
+
+/// The text carrying the verdict in a chat completion. Reasoning models may wrap their
+/// thoughts in `<think>` tags or move everything to `reasoning_content`.
+fn extract_chat_content(v: &Value) -> String {
+    let message = &v["choices"][0]["message"];
+    let content = strip_think(message["content"].as_str().unwrap_or(""));
+    if !content.trim().is_empty() {
+        return content;
+    }
+    ["reasoning_content", "reasoning"]
+        .iter()
+        .filter_map(|k| message[*k].as_str())
+        .find(|s| !s.trim().is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| "{}".into())
+}
+
+/// `output_text` of a Responses API reply: the convenience field when present,
+/// otherwise the `output_text` parts of the message items joined together.
+fn extract_output_text(v: &Value) -> String {
+    if let Some(text) = v["output_text"].as_str() {
+        return text.to_string();
+    }
+    let text: String = v["output"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|item| item["type"] == "message")
+        .flat_map(|item| item["content"].as_array().into_iter().flatten())
+        .filter(|part| part["type"] == "output_text")
+        .filter_map(|part| part["text"].as_str())
+        .collect();
+    if text.trim().is_empty() {
+        "{}".into()
+    } else {
+        text
+    }
+}
+
+fn strip_think(s: &str) -> String {
+    match (s.find("<think>"), s.rfind("</think>")) {
+        (Some(start), Some(end)) if end > start => {
+            format!("{}{}", &s[..start], &s[end + "</think>".len()..])
+        }
+        (None, Some(end)) => s[end + "</think>".len()..].to_string(),
+        _ => s.to_string(),
+    }
+}
@@ -0,0 +1,125 @@
+use crate::config::RateLimitConfig;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Token-bucket limiter for requests/min and tokens/min, shared by all clones of a client
+/// so concurrent workers draw from the same allowance.
+pub struct RateLimiter {
+    rpm: Option<f64>,
+    tpm: Option<f64>,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    requests: f64,
+    tokens: f64,
+    last: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(cfg: &RateLimitConfig) -> Self {
+        let rpm = cfg.requests_per_minute.map(|v| v.max(1) as f64);
+        let tpm = cfg.tokens_per_minute.map(|v| v.max(1) as f64);
+        Self {
+            rpm,
+            tpm,
+            state: Mutex::new(BucketState {
+                requests: rpm.unwrap_or(0.0),
+                tokens: tpm.unwrap_or(0.0),
+                last: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until one request carrying `tokens` estimated tokens fits in the buckets.
+    pub async fn acquire(&self, tokens: u64) {
+        loop {
+            let wait = {
+                let mut st = self.state.lock().await;
+                let now = Instant::now();
+                self.refill(&mut st, now);
+
+                if let Some(until) = st.paused_until {
+                    if until > now {
+                        until - now
+                    } else {
+                        st.paused_until = None;
+                        continue;
+                    }
+                } else {
+                    // A single request larger than the whole bucket only waits for a full bucket.
+                    let need_tokens = self.tpm.map(|cap| (tokens as f64).min(cap));
+                    let req_wait = self
+                        .rpm
+                        .map(|rate| deficit_wait(st.requests, 1.0, rate))
+                        .unwrap_or(Duration::ZERO);
+                    let tok_wait = match (self.tpm, need_tokens) {
+                        (Some(rate), Some(need)) => deficit_wait(st.tokens, need, rate),
+                        _ => Duration::ZERO,
+                    };
+                    let wait = req_wait.max(tok_wait);
+                    if wait.is_zero() {
+                        if self.rpm.is_some() {
+                            st.requests -= 1.0;
+                        }
+                        if let Some(need) = need_tokens {
+                            st.tokens -= need;
+                        }
+                        return;
+                    }
+                    wait
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Stop handing out requests until `until`, e.g. when the provider reports
+    /// an exhausted rate-limit window.
+    pub async fn pause_until(&self, until: Instant) {
+        let mut st = self.state.lock().await;
+        if st.paused_until.is_none_or(|p| p < until) {
+            st.paused_until = Some(until);
+        }
+    }
+
+    fn refill(&self, st: &mut BucketState, now: Instant) {
+        let minutes = now.duration_since(st.last).as_secs_f64() / 60.0;
+        st.last = now;
+        if let Some(rate) = self.rpm {
+            st.requests = (st.requests + rate * minutes).min(rate);
+        }
+        if let Some(rate) = self.tpm {
+            st.tokens = (st.tokens + rate * minutes).min(rate);
+        }
+    }
+}
+
+/// Time until a bucket refilling at `per_minute` holds `need`.
+fn deficit_wait(available: f64, need: f64, per_minute: f64) -> Duration {
+    if available >= need {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64((need - available) / per_minute * 60.0)
+}
+
+/// Rough token count for budgeting requests: one token per CJK character,
+/// about four characters per token otherwise.
+pub fn estimate_tokens(text: &str) -> u64 {
+    let (mut cjk, mut other) = (0u64, 0u64);
+    for c in text.chars() {
+        if is_cjk(c) {
+            cjk += 1;
+        } else {
+            other += 1;
+        }
+    }
+    cjk + other.div_ceil(4)
+}
+
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF)
+}
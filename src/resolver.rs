@@ -0,0 +1,298 @@
+use crate::config::ResolverConfig;
+use crate::db::Database;
+use crate::html::HtmlContent;
+use crate::links::{host_of, matches_domain, resolve};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, StatusCode, Url, header};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Failed resolutions are tried again after this long.
+const RETRY_FAILED_HOURS: i64 = 24;
+
+/// Follows shortened and redirecting links to where they end, one hop at a time,
+/// never connecting to private, loopback or link-local addresses.
+pub struct Resolver {
+    cfg: ResolverConfig,
+    /// Shortener and redirector domains from the link list and the config
+    domains: Vec<String>,
+    http: Client,
+    db: Database,
+}
+
+impl Resolver {
+    pub fn new(
+        cfg: &ResolverConfig,
+        redirectors: &[String],
+        user_agent: &str,
+        db: Database,
+    ) -> Result<Self> {
+        let http = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicDns))
+            .build()?;
+        let domains = redirectors
+            .iter()
+            .cloned()
+            .chain(cfg.domains.iter().map(|d| d.trim().to_lowercase()))
+            .collect();
+        Ok(Self {
+            cfg: cfg.clone(),
+            domains,
+            http,
+            db,
+        })
+    }
+
+    /// Links of an item worth following, at most `max_links`.
+    pub fn candidates(&self, html: &HtmlContent, base: Option<&str>) -> Vec<Url> {
+        let base = base.and_then(|b| Url::parse(b).ok());
+        let mut urls: Vec<Url> = Vec::new();
+        for link in &html.links {
+            let Some(url) = resolve(&link.href, base.as_ref()) else {
+                continue;
+            };
+            if (self.cfg.resolve_all || matches_domain(&host_of(&url), &self.domains))
+                && !urls.contains(&url)
+            {
+                urls.push(url);
+            }
+            if urls.len() >= self.cfg.max_links {
+                break;
+            }
+        }
+        urls
+    }
+
+    /// Where `url` ends after its redirects, from the cache or by following them.
+    /// `None` when it does not redirect or cannot be followed.
+    pub async fn destination(&self, url: &Url) -> Option<Url> {
+        let key = url.as_str();
+        match self.db.redirect_cached(key).await {
+            Ok(Some(cached)) => {
+                if let Some(final_url) = cached.final_url {
+                    return Url::parse(&final_url).ok().filter(|u| u != url);
+                }
+                let retry_after = DateTime::parse_from_rfc3339(&cached.resolved_at)
+                    .map(|t| t.with_timezone(&Utc) + chrono::Duration::hours(RETRY_FAILED_HOURS))
+                    .unwrap_or_default();
+                if Utc::now() < retry_after {
+                    debug!(url = key, error = ?cached.error, "redirect_cached_failure");
+                    return None;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!(url = key, error = %e, "redirect_cache_read_failed"),
+        }
+        let followed = self.follow(url).await;
+        let saved = match &followed {
+            Ok((final_url, hops)) => {
+                debug!(url = key, destination = %final_url, hops, "redirect_resolved");
+                self.db
+                    .save_redirect(key, Some(final_url.as_str()), *hops as i64, None)
+                    .await
+            }
+            Err(e) => {
+                warn!(url = key, error = %e, "redirect_resolve_failed");
+                self.db
+                    .save_redirect(key, None, 0, Some(&e.to_string()))
+                    .await
+            }
+        };
+        if let Err(e) = saved {
+            warn!(url = key, error = %e, "redirect_cache_save_failed");
+        }
+        followed.ok().map(|(u, _)| u).filter(|u| u != url)
+    }
+
+    /// Follow redirects from `url`, returning the last URL reached and the number of hops.
+    /// Once at least one hop was taken, a target that cannot be requested still counts as
+    /// the destination: it is known, just not visited.
+    async fn follow(&self, url: &Url) -> Result<(Url, usize)> {
+        let mut current = url.clone();
+        for hops in 0..=self.cfg.max_hops {
+            let next = match self.next_hop(&current).await {
+                Ok(next) => next,
+                Err(e) if hops > 0 => {
+                    debug!(url = %current, error = %e, "redirect_hop_failed");
+                    None
+                }
+                Err(e) => return Err(e),
+            };
+            match next {
+                Some(next) => current = next,
+                None => return Ok((current, hops)),
+            }
+        }
+        Err(anyhow!("redirect_too_many_hops: > {}", self.cfg.max_hops))
+    }
+
+    /// The `Location` of `url`, trying HEAD first and GET when HEAD is refused.
+    async fn next_hop(&self, url: &Url) -> Result<Option<Url>> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("redirect_unsupported_scheme: {}", url.scheme()));
+        }
+        ensure_public(url)?;
+        let resp = match self.http.head(url.clone()).send().await {
+            Ok(r)
+                if !matches!(
+                    r.status(),
+                    StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+                ) =>
+            {
+                r
+            }
+            _ => self.http.get(url.clone()).send().await?,
+        };
+        if !resp.status().is_redirection() {
+            return Ok(None);
+        }
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("redirect_missing_location: {}", resp.status()))?;
+        Ok(Some(url.join(location)?))
+    }
+}
+
+/// DNS lookups that drop private, loopback and link-local addresses, so a public
+/// name pointing inside the network cannot be reached.
+pub struct PublicDns;
+
+impl Resolve for PublicDns {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|a| is_public(a.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("private_address: {}", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Refuse URLs naming a non-public IP address directly; names are checked by `PublicDns`.
+pub fn ensure_public(url: &Url) -> Result<()> {
+    let ip = url
+        .host_str()
+        .and_then(|h| h.trim_matches(['[', ']']).parse::<IpAddr>().ok());
+    match ip {
+        Some(ip) if !is_public(ip) => Err(anyhow!("private_address: {}", ip)),
+        _ => Ok(()),
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => {
+                let [a, b, ..] = ip.segments();
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || (a == 0x2001 && b == 0xdb8))
+            }
+        },
+    }
+}
+
+/// The IPv4 address an IPv6 address stands for: IPv4-mapped (`::ffff:0:0/96`),
+/// NAT64 (`64:ff9b::/96`) and 6to4 (`2002::/16`) all reach it in the end.
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let o = ip.octets();
+    match ip.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, ..] => Some(Ipv4Addr::new(o[12], o[13], o[14], o[15])),
+        [0x2002, ..] => Some(Ipv4Addr::new(o[2], o[3], o[4], o[5])),
+        _ => ip.to_ipv4_mapped(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn ipv4_ranges() {
+        assert!(public("93.184.216.34"));
+        for ip in [
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "198.18.0.1",
+            "192.0.2.1",
+            "240.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn ipv6_ranges() {
+        assert!(public("2606:2800:220:1::1"));
+        for ip in ["::1", "::", "fc00::1", "fe80::1", "ff02::1", "2001:db8::1"] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn embedded_ipv4_is_unwrapped() {
+        assert!(!public("::ffff:127.0.0.1"));
+        assert!(public("::ffff:93.184.216.34"));
+        assert!(!public("64:ff9b::10.0.0.1"));
+        assert!(!public("64:ff9b::a9fe:a9fe"));
+        assert!(public("64:ff9b::93.184.216.34"));
+        assert!(!public("2002:c0a8:101::1"));
+        assert!(!public("2002:7f00:1::"));
+        assert!(public("2002:5db8:d822::1"));
+    }
+
+    #[test]
+    fn ensure_public_checks_literal_hosts() {
+        let check = |u: &str| ensure_public(&Url::parse(u).unwrap()).is_ok();
+        assert!(check("https://example.com/"));
+        assert!(check("https://93.184.216.34/"));
+        assert!(!check("http://127.0.0.1:8080/"));
+        assert!(!check("http://[::1]/"));
+        assert!(!check("http://[::ffff:10.0.0.1]/"));
+        assert!(!check("http://[64:ff9b::7f00:1]/"));
+        assert!(!check("http://[2002:a00:1::]/"));
+        assert!(!check("http://[2001:db8::1]/"));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::config::RulesConfig;
+use crate::links::{LinkFeatures, matches_domain};
+
+/// Provider name recorded for verdicts decided by a rule instead of the classifier.
+pub const RULES_PROVIDER: &str = "rules";
+
+/// A verdict reached without the classifier.
+#[derive(Debug, Clone)]
+pub struct RuleHit {
+    pub is_ad: bool,
+    pub reason: String,
+}
+
+/// Signals available to rules.
+#[derive(Debug, Clone, Copy)]
+pub struct RuleInput<'a> {
+    pub links: &'a LinkFeatures,
+    /// Title and text as stored with the review
+    pub text: &'a str,
+    pub language: Option<&'a str>,
+}
+
+/// The first configured rule that decides the item, if any. Items in a language with
+/// its own rule pack are checked against that pack only.
+pub fn evaluate(cfg: &RulesConfig, input: RuleInput) -> Option<RuleHit> {
+    let cfg = input
+        .language
+        .and_then(|l| cfg.languages.get(l))
+        .unwrap_or(cfg);
+    if !cfg.keywords.is_empty() {
+        let text = input.text.to_lowercase();
+        if let Some(keyword) = cfg
+            .keywords
+            .iter()
+            .find(|k| !k.is_empty() && text.contains(&k.to_lowercase()))
+        {
+            return Some(RuleHit {
+                is_ad: true,
+                reason: format!("rule: keyword \"{}\"", keyword),
+            });
+        }
+    }
+    if let Some(min) = cfg.affiliate_score
+        && input.links.score >= min
+    {
+        return Some(RuleHit {
+            is_ad: true,
+            reason: format!(
+                "rule: affiliate_score {:.2} >= {:.2} ({} affiliate of {} links)",
+                input.links.score, min, input.links.affiliate, input.links.links
+            ),
+        });
+    }
+    if cfg.adblock
+        && let Some(first) = input.links.blocked.first()
+    {
+        return Some(RuleHit {
+            is_ad: true,
+            reason: format!(
+                "rule: adblock {} matched {} ({} blocked)",
+                first.filter,
+                first.url,
+                input.links.blocked.len()
+            ),
+        });
+    }
+    if let Some(dest) = input
+        .links
+        .destinations
+        .iter()
+        .find(|d| matches_domain(d, &cfg.destination_domains))
+    {
+        return Some(RuleHit {
+            is_ad: true,
+            reason: format!(
+                "rule: destination {} ({} redirected links)",
+                dest, input.links.redirected
+            ),
+        });
+    }
+    None
+}
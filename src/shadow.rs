@@ -0,0 +1,71 @@
+use crate::{config::OpenAiConfig, db::Database, openai_client::ClassifierVersion};
+use anyhow::Result;
+
+/// Print how often the shadow classifier agrees with the primary one, and where it doesn't.
+pub async fn report(
+    db: &Database,
+    primary: &OpenAiConfig,
+    shadow: &OpenAiConfig,
+    limit: usize,
+) -> Result<()> {
+    let version = ClassifierVersion::of(shadow);
+    let pairs = db.shadow_pairs(&version).await?;
+    let (other, unpaired) = db.shadow_skipped(&version).await?;
+    println!(
+        "主分类器: model={} threshold={:.2} | 影子分类器: model={} threshold={:.2} prompt={}",
+        primary.model,
+        primary.threshold,
+        shadow.model,
+        shadow.threshold,
+        &shadow.prompt_fingerprint()[..8],
+    );
+    if other > 0 || unpaired > 0 {
+        println!(
+            "已跳过影子审查: 其他提示词或模型={} 无主审查={}",
+            other, unpaired
+        );
+    }
+    if pairs.is_empty() {
+        println!("暂无影子审查记录");
+        return Ok(());
+    }
+
+    let disagreements: Vec<_> = pairs
+        .iter()
+        .filter(|p| {
+            (p.is_ad && p.confidence >= primary.threshold)
+                != (p.shadow_is_ad && p.shadow_confidence >= shadow.threshold)
+        })
+        .collect();
+    let raw_agree = pairs.iter().filter(|p| p.is_ad == p.shadow_is_ad).count();
+    let total = pairs.len();
+    println!(
+        "样本={} 判定一致率={:.1}% (is_ad 一致率={:.1}%) 不一致={}",
+        total,
+        100.0 * (total - disagreements.len()) as f64 / total as f64,
+        100.0 * raw_agree as f64 / total as f64,
+        disagreements.len(),
+    );
+
+    for p in disagreements.iter().take(limit) {
+        println!();
+        println!(
+            "#{} {}",
+            p.item_id,
+            p.title.as_deref().unwrap_or("(无标题)")
+        );
+        println!(
+            "  主: is_ad={} confidence={:.2} | {}",
+            p.is_ad, p.confidence, p.reason
+        );
+        println!(
+            "  影: is_ad={} confidence={:.2} | {}",
+            p.shadow_is_ad, p.shadow_confidence, p.shadow_reason
+        );
+    }
+    if disagreements.len() > limit {
+        println!();
+        println!("... 另有 {} 条未显示", disagreements.len() - limit);
+    }
+    Ok(())
+}
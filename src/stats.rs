@@ -0,0 +1,62 @@
+use crate::{budget::period_starts, config::Config, db::Database};
+use anyhow::Result;
+
+/// Print review totals, LLM spend against the budget caps and recent run summaries.
+pub async fn show(cfg: &Config, db: &Database, runs: usize) -> Result<()> {
+    println!("== 审查 ==");
+    for (action, count) in db.action_counts().await? {
+        println!("  {:<16} {}", action, count);
+    }
+
+    let (day, month) = period_starts();
+    let today = db.usage_since(&day).await?;
+    let this_month = db.usage_since(&month).await?;
+    println!();
+    println!("== 费用 ==");
+    for (name, totals, cap) in [
+        ("今日", &today, cfg.budget.daily_usd),
+        ("本月", &this_month, cfg.budget.monthly_usd),
+    ] {
+        println!(
+            "  {} 调用={} tokens={}/{} (缓存 {}) 费用=${:.4}{}",
+            name,
+            totals.calls,
+            totals.prompt_tokens,
+            totals.completion_tokens,
+            totals.cached_tokens,
+            totals.cost,
+            match cap {
+                Some(cap) if totals.cost >= cap => format!(" / ${:.2} 已用尽", cap),
+                Some(cap) => format!(" / ${:.2}", cap),
+                None => String::new(),
+            }
+        );
+    }
+
+    let recent = db.recent_runs(runs).await?;
+    if !recent.is_empty() {
+        println!();
+        println!("== 最近运行 ==");
+        for r in recent {
+            println!(
+                "  {} 总数={} 保留={} 处理={} 推荐={} 推迟={} 超长={} 出错={} 宽松解析={} 修复={} 解析失败={} tokens={}/{} 费用=${:.4}{}",
+                r.started_at,
+                r.total,
+                r.kept,
+                r.acted,
+                r.promoted,
+                r.deferred,
+                r.oversized,
+                r.errors,
+                r.lenient_parses,
+                r.repairs,
+                r.parse_failures,
+                r.prompt_tokens,
+                r.completion_tokens,
+                r.cost,
+                r.note.map(|n| format!(" | {}", n)).unwrap_or_default(),
+            );
+        }
+    }
+    Ok(())
+}
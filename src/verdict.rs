@@ -0,0 +1,407 @@
+use crate::openai_client::{ClassifierResponse, FilterVerdict, TokenUsage};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::warn;
+
+/// Follow-up sent once when a reply cannot be parsed, together with the invalid reply.
+pub const REPAIR_PROMPT: &str = "Your previous reply was invalid. Reply again with only a JSON object: {\"is_ad\": boolean, \"confidence\": number between 0 and 1, \"reason\": string}. No prose, no code fences.";
+
+/// How a verdict was recovered from the model output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerdictParse {
+    /// Valid JSON in the expected shape
+    Strict,
+    /// Needed extraction from prose or type coercion
+    Lenient,
+    /// Only parsed after the repair round-trip
+    Repaired,
+}
+
+/// The model output could not be turned into a verdict, even leniently.
+#[derive(Debug, Clone)]
+pub struct VerdictParseError {
+    pub detail: String,
+    pub raw: String,
+    /// Tokens spent on the failed attempts, so they can still be charged
+    pub usage: TokenUsage,
+    /// Model that produced the reply, once known
+    pub model: String,
+}
+
+impl fmt::Display for VerdictParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "parse_classifier_response_failed: {} raw={}",
+            self.detail, self.raw
+        )
+    }
+}
+
+impl std::error::Error for VerdictParseError {}
+
+/// Parse a classifier reply, first strictly and then leniently.
+pub fn parse(raw: &str) -> Result<(ClassifierResponse, VerdictParse), VerdictParseError> {
+    let content = strip_code_fences(raw);
+    let (mut verdict, how) = match parse_strict(&content) {
+        Ok(v) => (v, VerdictParse::Strict),
+        Err(strict_err) => match parse_lenient(&content) {
+            Some(v) => {
+                warn!(error = %strict_err, "classifier_response_parsed_leniently");
+                (v, VerdictParse::Lenient)
+            }
+            None => {
+                return Err(VerdictParseError {
+                    detail: strict_err,
+                    raw: raw.to_string(),
+                    usage: TokenUsage::default(),
+                    model: String::new(),
+                });
+            }
+        },
+    };
+    verdict.confidence = normalize_score(verdict.confidence).ok_or_else(|| VerdictParseError {
+        detail: format!("confidence_out_of_range: {}", verdict.confidence),
+        raw: raw.to_string(),
+        usage: TokenUsage::default(),
+        model: String::new(),
+    })?;
+    verdict
+        .filters
+        .retain(|name, f| match normalize_score(f.confidence) {
+            Some(c) => {
+                f.confidence = c;
+                true
+            }
+            None => {
+                warn!(filter = %name, confidence = f.confidence, "filter_confidence_out_of_range");
+                false
+            }
+        });
+    if let Some(interest) = verdict.interest {
+        verdict.interest = normalize_score(interest);
+        if verdict.interest.is_none() {
+            warn!(interest, "interest_out_of_range");
+        }
+    }
+    Ok((verdict, how))
+}
+
+/// A score on the 0-1 scale. Some models answer in whole percent, so integers up to 100
+/// are read as percentages; anything else is clamped to 0-1. Only NaN is rejected.
+fn normalize_score(score: f32) -> Option<f32> {
+    if score.is_nan() {
+        return None;
+    }
+    let score = if score > 1.0 && score <= 100.0 && score.fract() == 0.0 {
+        score / 100.0
+    } else {
+        score
+    };
+    Some(score.clamp(0.0, 1.0))
+}
+
+fn parse_strict(content: &str) -> Result<ClassifierResponse, String> {
+    match serde_json::from_str::<ClassifierResponse>(content) {
+        Ok(parsed) => Ok(parsed),
+        Err(primary_err) => {
+            let responses: Vec<ClassifierResponse> =
+                serde_json::from_str(content).map_err(|secondary_err| {
+                    format!("{} (array_parse_error: {})", primary_err, secondary_err)
+                })?;
+            if !responses.is_empty() {
+                warn!(
+                    error = %primary_err,
+                    count = responses.len(),
+                    "classifier_response_array_detected"
+                );
+            }
+            pick_best(responses).ok_or_else(|| "empty array".to_string())
+        }
+    }
+}
+
+/// When a model returns several verdicts, the most confident ad verdict wins,
+/// otherwise the most confident one.
+fn pick_best(responses: Vec<ClassifierResponse>) -> Option<ClassifierResponse> {
+    if let Some(best_ad) = responses
+        .iter()
+        .filter(|r| r.is_ad)
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    {
+        return Some(best_ad.clone());
+    }
+    responses
+        .into_iter()
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+}
+
+/// Find JSON values embedded in prose, drop trailing commas and coerce field types.
+fn parse_lenient(content: &str) -> Option<ClassifierResponse> {
+    let mut from = 0;
+    while let Some(offset) = content[from..].find(['{', '[']) {
+        let start = from + offset;
+        if let Some(end) = balanced_end(&content[start..]) {
+            let candidate = remove_trailing_commas(&content[start..start + end]);
+            if let Ok(v) = serde_json::from_str::<Value>(&candidate) {
+                let found = match &v {
+                    Value::Array(items) => pick_best(items.iter().filter_map(coerce).collect()),
+                    _ => coerce(&v),
+                };
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+        from = start + 1;
+    }
+    None
+}
+
+/// Length of the bracketed value at the start of `s`, skipping brackets inside strings.
+fn balanced_end(s: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn remove_trailing_commas(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let chars: Vec<char> = s.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn coerce(v: &Value) -> Option<ClassifierResponse> {
+    let obj = v.as_object()?;
+    let is_ad = match obj.get("is_ad")? {
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64()? != 0.0,
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" | "ad" | "是" => true,
+            "false" | "no" | "n" | "0" | "not_ad" | "否" => false,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let confidence = coerce_score(obj.get("confidence")?)?;
+    let reason = match obj.get("reason") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    };
+    Some(ClassifierResponse {
+        is_ad,
+        confidence: confidence as f32,
+        reason,
+        filters: obj.get("filters").map(coerce_filters).unwrap_or_default(),
+        interest: obj.get("interest").and_then(coerce_score).map(|i| i as f32),
+    })
+}
+
+/// A score between 0 and 1 given as a number, a numeric string or a percentage.
+fn coerce_score(v: &Value) -> Option<f64> {
+    let score = match v {
+        Value::Number(n) => n.as_f64()?,
+        Value::String(s) => {
+            let s = s.trim();
+            match s.strip_suffix('%') {
+                Some(pct) => pct.trim().parse::<f64>().ok()? / 100.0,
+                None => s.parse::<f64>().ok()?,
+            }
+        }
+        _ => return None,
+    };
+    Some(score)
+}
+
+/// Filter verdicts given as `{"match": .., "confidence": ..}`, a bare boolean or a bare
+/// confidence. Entries that cannot be read are dropped.
+fn coerce_filters(v: &Value) -> HashMap<String, FilterVerdict> {
+    let Some(obj) = v.as_object() else {
+        return HashMap::new();
+    };
+    obj.iter()
+        .filter_map(|(name, v)| {
+            let verdict = match v {
+                Value::Bool(b) => FilterVerdict {
+                    matched: *b,
+                    confidence: 1.0,
+                },
+                Value::Number(n) => {
+                    let confidence = n.as_f64()? as f32;
+                    FilterVerdict {
+                        matched: confidence >= 0.5,
+                        confidence,
+                    }
+                }
+                Value::Object(o) => FilterVerdict {
+                    matched: o
+                        .get("match")
+                        .or_else(|| o.get("matched"))
+                        .and_then(Value::as_bool)?,
+                    confidence: o.get("confidence").and_then(Value::as_f64).unwrap_or(1.0) as f32,
+                },
+                _ => return None,
+            };
+            Some((name.clone(), verdict))
+        })
+        .collect()
+}
+
+fn strip_code_fences(s: &str) -> String {
+    let t = s.trim();
+    if t.starts_with("```") {
+        // remove first line fence and trailing fence
+        let mut lines = t.lines();
+        let _first = lines.next();
+        let rest: String = lines.collect::<Vec<_>>().join("\n");
+        let trimmed = rest.trim_end();
+        if trimmed.ends_with("```") {
+            return trimmed.trim_end_matches("```").trim().to_string();
+        }
+        return trimmed.to_string();
+    }
+    t.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn confidence(raw: &str) -> f32 {
+        parse(raw).expect("parses").0.confidence
+    }
+
+    #[test]
+    fn strict_json() {
+        let (v, how) =
+            parse(r#"{"is_ad": true, "confidence": 0.8, "reason": "sponsored"}"#).unwrap();
+        assert!(v.is_ad);
+        assert_eq!(v.confidence, 0.8);
+        assert_eq!(v.reason, "sponsored");
+        assert_eq!(how, VerdictParse::Strict);
+    }
+
+    #[test]
+    fn code_fences_are_stripped() {
+        let raw = "```json\n{\"is_ad\": false, \"confidence\": 0.1, \"reason\": \"\"}\n```";
+        assert_eq!(parse(raw).unwrap().1, VerdictParse::Strict);
+    }
+
+    #[test]
+    fn lenient_prose_trailing_commas_and_strings() {
+        let raw = r#"Sure! Here it is: {"is_ad": "yes", "confidence": "0.7", "reason": "promo",} Thanks."#;
+        let (v, how) = parse(raw).unwrap();
+        assert!(v.is_ad);
+        assert_eq!(v.confidence, 0.7);
+        assert_eq!(how, VerdictParse::Lenient);
+        assert!(
+            !parse(r#"{"is_ad": "否", "confidence": 0.2}"#)
+                .unwrap()
+                .0
+                .is_ad
+        );
+    }
+
+    #[test]
+    fn arrays_pick_the_most_confident_ad() {
+        let raw = r#"[{"is_ad": false, "confidence": 0.9, "reason": ""},
+                      {"is_ad": true, "confidence": 0.6, "reason": ""},
+                      {"is_ad": true, "confidence": 0.7, "reason": ""}]"#;
+        let (v, _) = parse(raw).unwrap();
+        assert!(v.is_ad);
+        assert_eq!(v.confidence, 0.7);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(
+            confidence(r#"{"is_ad": true, "confidence": 85, "reason": ""}"#),
+            0.85
+        );
+        assert_eq!(
+            confidence(r#"{"is_ad": true, "confidence": "85%", "reason": ""}"#),
+            0.85
+        );
+        assert_eq!(
+            confidence(r#"{"is_ad": true, "confidence": 100, "reason": ""}"#),
+            1.0
+        );
+        assert_eq!(
+            confidence(r#"{"is_ad": true, "confidence": 1, "reason": ""}"#),
+            1.0
+        );
+    }
+
+    #[test]
+    fn out_of_range_scores_are_clamped() {
+        for (raw, want) in [("1.05", 1.0), ("-0.01", 0.0), ("100.5", 1.0), ("250", 1.0)] {
+            let reply = format!(r#"{{"is_ad": true, "confidence": {raw}, "reason": ""}}"#);
+            assert_eq!(confidence(&reply), want, "{raw}");
+        }
+        assert_eq!(normalize_score(f32::NAN), None);
+    }
+
+    #[test]
+    fn filters_and_interest() {
+        let raw = r#"{"is_ad": false, "confidence": 0.1, "reason": "",
+                      "filters": {"crypto": {"match": true, "confidence": 90}, "sports": false},
+                      "interest": 1.2}"#;
+        let (v, _) = parse(raw).unwrap();
+        assert!(v.filters["crypto"].matched);
+        assert_eq!(v.filters["crypto"].confidence, 0.9);
+        assert!(!v.filters["sports"].matched);
+        assert_eq!(v.interest, Some(1.0));
+    }
+
+    #[test]
+    fn unparseable_replies_fail() {
+        assert!(parse("I think this is an ad.").is_err());
+        assert!(parse(r#"{"confidence": 0.5}"#).is_err());
+        assert!(parse("[]").is_err());
+    }
+}